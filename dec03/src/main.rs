@@ -1,123 +1,281 @@
-use std::collections::HashMap;
+use std::{collections::{BTreeMap, HashMap}, env, fs, ops::Range};
 
 use input_curler::input_for;
 use regex::Regex;
 
-fn main() {
-    let data = input_for(3).unwrap();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumberStatus {
+    Counted,
+    Ignored
+}
 
-    let answer_one = part_one(&data);
-    println!("Part one: {}", answer_one);
+#[derive(Debug, Clone)]
+struct SchematicNumber {
+    row: usize,
+    cols: Range<usize>,
+    value: u32,
+    status: NumberStatus
+}
 
-    let answer_two = part_two(&data);
-    println!("Part two: {}", answer_two);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Annotation {
+    Blank,
+    Counted,
+    Ignored,
+    Gear,
+    Symbol
 }
 
-fn part_one(data: &String) -> u32 {
-    let mut symbol_adjacent_locations: Vec<(usize, usize)> = vec![];
-
-    let height = data.lines().count();
-    let width = data.lines().next().unwrap().chars().count();
-    for (row_num, line) in data.lines().enumerate() {
-        for (col_num, cell) in line.chars().enumerate() {
-            if !cell.is_ascii_digit() && cell != '.' {
-                let cols = if col_num == 0 {
-                    vec![0, 1]
-                } else if col_num == width - 1 {
-                    vec![width - 2, width - 1]
-                } else {
-                    vec![col_num - 1, col_num, col_num + 1]
-                };
-                let rows = if row_num == 0 {
-                    vec![0, 1]
-                } else if row_num == height - 1 {
-                    vec![height - 2, height - 1]
-                } else {
-                    vec![row_num - 1, row_num, row_num + 1]
-                };
-                cols.iter().for_each(|&col|
-                    rows.iter().for_each(|&row|
-                        if col != col_num || row != row_num {
-                            symbol_adjacent_locations.push((row, col));
+struct Schematic {
+    cells: Vec<Vec<char>>,
+    numbers: Vec<SchematicNumber>,
+    // Every '*', with the indices of the numbers adjacent to it
+    stars: BTreeMap<(usize, usize), Vec<usize>>
+}
+
+impl Schematic {
+    fn parse(data: &str) -> Self {
+        let cells = data.lines().map(|line| line.chars().collect::<Vec<char>>()).collect::<Vec<_>>();
+        let is_symbol = |row: usize, col: usize| {
+            cells.get(row)
+                .and_then(|line| line.get(col))
+                .is_some_and(|&cell| !cell.is_ascii_digit() && cell != '.')
+        };
+
+        let mut numbers = vec![];
+        let mut stars = BTreeMap::<(usize, usize), Vec<usize>>::new();
+        let num_re = Regex::new(r"\d+").unwrap();
+        for (row_num, line) in data.lines().enumerate() {
+            for num_match in num_re.find_iter(line) {
+                let cols = num_match.start()..num_match.end();
+                let rows = row_num.saturating_sub(1)..=(row_num + 1);
+                let neighbours = rows.flat_map(|row|
+                    (cols.start.saturating_sub(1)..=cols.end).map(move |col| (row, col))
+                ).filter(|&(row, col)| row != row_num || !cols.contains(&col));
+
+                let mut counted = false;
+                for (row, col) in neighbours {
+                    if is_symbol(row, col) {
+                        counted = true;
+                        if cells[row][col] == '*' {
+                            stars.entry((row, col)).or_default().push(numbers.len());
                         }
-                    )
-                );
+                    }
+                }
+
+                numbers.push(SchematicNumber {
+                    row: row_num,
+                    cols,
+                    value: num_match.as_str().parse::<u32>().unwrap(),
+                    status: if counted { NumberStatus::Counted } else { NumberStatus::Ignored }
+                });
             }
         }
-    }
 
-    let mut sum = 0;
-    let num_re = Regex::new(r"\d+").unwrap();
-    for (row_num, line) in data.lines().enumerate() {
-        for num_match in num_re.find_iter(line) {
-            let start = num_match.start();
-            let range = start..start + num_match.len();
-            if symbol_adjacent_locations.iter().any(|&loc| loc.0 == row_num && range.contains(&loc.1)) {
-                sum += num_match.as_str().parse::<u32>().unwrap();
+        for (row, line) in cells.iter().enumerate() {
+            for (col, _) in line.iter().enumerate().filter(|(_, &cell)| cell == '*') {
+                stars.entry((row, col)).or_default();
             }
         }
+
+        Self { cells, numbers, stars }
     }
-    sum
-}
 
-fn part_two(data: &String) -> u32 {
-    let mut symbol_adjacent_locations = HashMap::<(usize, usize), Vec::<(usize, usize)>>::new();
-
-    let height = data.lines().count();
-    let width = data.lines().next().unwrap().chars().count();
-    for (row_num, line) in data.lines().enumerate() {
-        for (col_num, cell) in line.chars().enumerate() {
-            if cell == '*' {
-                let cols = if col_num == 0 {
-                    vec![0, 1]
-                } else if col_num == width - 1 {
-                    vec![width - 2, width - 1]
-                } else {
-                    vec![col_num - 1, col_num, col_num + 1]
-                };
-                let rows = if row_num == 0 {
-                    vec![0, 1]
-                } else if row_num == height - 1 {
-                    vec![height - 2, height - 1]
-                } else {
-                    vec![row_num - 1, row_num, row_num + 1]
-                };
-                symbol_adjacent_locations.insert((row_num, col_num), vec![]);
-                let adjacencies = symbol_adjacent_locations.get_mut(&(row_num, col_num)).unwrap();
-                cols.iter().for_each(|&col|
-                    rows.iter().for_each(|&row|
-                        if col != col_num || row != row_num {
-                            adjacencies.push((row, col));
-                        }
-                    )
-                );
+    fn gears(&self) -> impl Iterator<Item = (&(usize, usize), &Vec<usize>)> {
+        self.stars.iter().filter(|(_, adjacent)| adjacent.len() == 2)
+    }
+
+    fn part_number_sum(&self) -> u32 {
+        self.numbers.iter()
+            .filter(|number| number.status == NumberStatus::Counted)
+            .map(|number| number.value)
+            .sum()
+    }
+
+    fn gear_ratio_sum(&self) -> u32 {
+        self.gears()
+            .map(|(_, adjacent)| adjacent.iter().map(|&ix| self.numbers[ix].value).product::<u32>())
+            .sum()
+    }
+
+    fn annotations(&self) -> Vec<Vec<Annotation>> {
+        let mut annotations = self.cells.iter().map(|line|
+            line.iter().map(|&cell| if cell == '.' || cell.is_ascii_digit() {
+                Annotation::Blank
+            } else {
+                Annotation::Symbol
+            }).collect::<Vec<_>>()
+        ).collect::<Vec<_>>();
+
+        for number in &self.numbers {
+            let annotation = match number.status {
+                NumberStatus::Counted => Annotation::Counted,
+                NumberStatus::Ignored => Annotation::Ignored
+            };
+            annotations[number.row][number.cols.clone()].fill(annotation);
+        }
+        for ((row, col), _) in self.gears() {
+            annotations[*row][*col] = Annotation::Gear;
+        }
+
+        annotations
+    }
+
+    fn render_ansi(&self) -> String {
+        let mut output = String::new();
+        for (line, annotation_line) in self.cells.iter().zip(self.annotations()) {
+            let mut current = Annotation::Blank;
+            for (&cell, annotation) in line.iter().zip(annotation_line) {
+                if annotation != current {
+                    output.push_str("\x1b[0m");
+                    output.push_str(match annotation {
+                        Annotation::Blank => "",
+                        Annotation::Counted => "\x1b[1;32m",
+                        Annotation::Ignored => "\x1b[2;31m",
+                        Annotation::Gear => "\x1b[1;33m",
+                        Annotation::Symbol => "\x1b[36m"
+                    });
+                    current = annotation;
+                }
+                output.push(cell);
             }
+            output.push_str("\x1b[0m\n");
         }
+        output
     }
 
-    let mut possible_cogs = HashMap::<(usize, usize), Vec<u32>>::new();
-    let num_re = Regex::new(r"\d+").unwrap();
-    for (row_num, line) in data.lines().enumerate() {
-        for num_match in num_re.find_iter(line) {
-            let start = num_match.start();
-            let range = start..start + num_match.len();
-            symbol_adjacent_locations
-                .iter()
-                .filter(|&(_, locs)| locs.iter().any(|loc| loc.0 == row_num && range.contains(&loc.1)))
-                .for_each(|(sym_loc, _)| {
-                    let num = num_match.as_str().parse::<u32>().unwrap();
-                    if let Some(numbers) = possible_cogs.get_mut(sym_loc) {
-                        numbers.push(num);
-                    } else {
-                        possible_cogs.insert(*sym_loc, vec![num]);
+    fn render_html(&self) -> String {
+        let mut output = concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<style>\n",
+            "pre { background: #0f0f23; color: #666; }\n",
+            ".counted { color: #00cc00; font-weight: bold; }\n",
+            ".ignored { color: #cc0000; }\n",
+            ".gear { color: #ffff66; font-weight: bold; }\n",
+            ".symbol { color: #00cccc; }\n",
+            "</style>\n</head>\n<body>\n<pre>\n"
+        ).to_string();
+
+        for (line, annotation_line) in self.cells.iter().zip(self.annotations()) {
+            let mut current = Annotation::Blank;
+            for (&cell, annotation) in line.iter().zip(annotation_line) {
+                if annotation != current {
+                    if current != Annotation::Blank {
+                        output.push_str("</span>");
                     }
-            });
+                    output.push_str(match annotation {
+                        Annotation::Blank => "",
+                        Annotation::Counted => "<span class=\"counted\">",
+                        Annotation::Ignored => "<span class=\"ignored\">",
+                        Annotation::Gear => "<span class=\"gear\">",
+                        Annotation::Symbol => "<span class=\"symbol\">"
+                    });
+                    current = annotation;
+                }
+                match cell {
+                    '&' => output.push_str("&amp;"),
+                    '<' => output.push_str("&lt;"),
+                    '>' => output.push_str("&gt;"),
+                    _ => output.push(cell)
+                }
+            }
+            if current != Annotation::Blank {
+                output.push_str("</span>");
+            }
+            output.push('\n');
+        }
+
+        output.push_str("</pre>\n</body>\n</html>\n");
+        output
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct StatusChange {
+    row: usize,
+    col: usize,
+    value: u32,
+    before: Option<NumberStatus>,
+    after: Option<NumberStatus>
+}
+
+// Numbers are matched up by position and value; a number that only exists in one
+// schematic is reported with `None` for the other side.
+fn diff(before: &Schematic, after: &Schematic) -> Vec<StatusChange> {
+    let index = |schematic: &Schematic| schematic.numbers.iter()
+        .map(|number| ((number.row, number.cols.start, number.value), number.status))
+        .collect::<HashMap<_, _>>();
+    let before_statuses = index(before);
+    let after_statuses = index(after);
+
+    let mut keys = before_statuses.keys().chain(after_statuses.keys()).copied().collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter().filter_map(|key| {
+        let (row, col, value) = key;
+        let before = before_statuses.get(&key).copied();
+        let after = after_statuses.get(&key).copied();
+        (before != after).then_some(StatusChange { row, col, value, before, after })
+    }).collect()
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["--diff", before_path, after_path] => {
+            let before = Schematic::parse(&fs::read_to_string(before_path).unwrap());
+            let after = Schematic::parse(&fs::read_to_string(after_path).unwrap());
+            for change in diff(&before, &after) {
+                println!("{} at ({}, {}): {:?} -> {:?}", change.value, change.row, change.col, change.before, change.after);
+            }
+        },
+        ["--render"] => {
+            let schematic = Schematic::parse(&input_for(3).unwrap());
+            print!("{}", schematic.render_ansi());
+        },
+        ["--html", output_path] => {
+            let schematic = Schematic::parse(&input_for(3).unwrap());
+            fs::write(output_path, schematic.render_html()).unwrap();
+        },
+        _ => {
+            let data = input_for(3).unwrap();
+
+            let answer_one = part_one(&data);
+            println!("Part one: {}", answer_one);
+
+            let answer_two = part_two(&data);
+            println!("Part two: {}", answer_two);
         }
     }
+}
 
-    possible_cogs.iter().filter_map(|(_, numbers)| if numbers.len() == 2 {
-        Some(numbers.iter().product::<u32>())
-    } else {
-        None
-    }).sum()
+fn part_one(data: &str) -> u32 {
+    Schematic::parse(data).part_number_sum()
+}
+
+fn part_two(data: &str) -> u32 {
+    Schematic::parse(data).gear_ratio_sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "467..114..\n...*......\n..35..633.\n......#...\n617*......\n.....+.58.\n..592.....\n......755.\n...$.*....\n.664.598..";
+
+    #[test]
+    fn test_example_answers() {
+        assert_eq!(part_one(EXAMPLE), 4361);
+        assert_eq!(part_two(EXAMPLE), 467835);
+    }
+
+    #[test]
+    fn test_diff_reports_changed_status() {
+        let before = Schematic::parse(EXAMPLE);
+        let after = Schematic::parse(&EXAMPLE.replacen('#', ".", 1));
+        let changes = diff(&before, &after);
+        assert_eq!(changes, vec![
+            StatusChange { row: 2, col: 6, value: 633, before: Some(NumberStatus::Counted), after: Some(NumberStatus::Ignored) }
+        ]);
+    }
 }
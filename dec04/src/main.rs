@@ -1,55 +1,338 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, env, time::Instant};
 
 use input_curler::input_for;
 
+// Card numbers are all below 100, so a set of them fits in the bits of a u128
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct NumberSet(u128);
+impl NumberSet {
+    fn insert(&mut self, number: u32) {
+        assert!(number < 128, "{} doesn't fit in a NumberSet", number);
+        self.0 |= 1 << number;
+    }
+
+    fn intersection(self, other: NumberSet) -> NumberSet {
+        NumberSet(self.0 & other.0)
+    }
+
+    fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    // Reads whitespace-separated numbers straight into the bitset
+    fn parse(text: &str) -> NumberSet {
+        let mut set = NumberSet::default();
+        let mut current: Option<u32> = None;
+        for byte in text.bytes() {
+            if byte.is_ascii_digit() {
+                current = Some(current.unwrap_or(0) * 10 + (byte - b'0') as u32);
+            } else if let Some(number) = current.take() {
+                set.insert(number);
+            }
+        }
+        if let Some(number) = current {
+            set.insert(number);
+        }
+        set
+    }
+}
+
 struct Scratchcard
 {
     id: u32,
+    winners: NumberSet,
+    owned: NumberSet
+}
+impl Scratchcard {
+    fn parse(line: &str) -> Self {
+        let (name_part, numbers_part) = line.split_once(':').unwrap();
+        let id = name_part.trim_start_matches("Card").trim().parse::<u32>().unwrap();
+        let (winner_part, owned_part) = numbers_part.split_once('|').unwrap();
+
+        Scratchcard { id, winners: NumberSet::parse(winner_part), owned: NumberSet::parse(owned_part) }
+    }
+
+    fn matches(&self) -> usize {
+        self.winners.intersection(self.owned).count() as usize
+    }
+}
+
+// The original representation, kept so the bitset can be benchmarked against it
+struct HashSetScratchcard
+{
     winners: HashSet<u32>,
     owned: HashSet<u32>
 }
+impl HashSetScratchcard {
+    fn parse(line: &str) -> Self {
+        let (_, numbers_part) = line.split_once(':').unwrap();
+        let (winner_part, owned_part) = numbers_part.split_once('|').unwrap();
+
+        let winners = winner_part.split_whitespace().map(|n| n.parse::<u32>().unwrap()).collect::<HashSet<u32>>();
+        let owned = owned_part.split_whitespace().map(|n| n.parse::<u32>().unwrap()).collect::<HashSet<u32>>();
+
+        HashSetScratchcard { winners, owned }
+    }
+
+    fn matches(&self) -> usize {
+        self.winners.intersection(&self.owned).count()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CopyRule {
+    // Win a copy of each of the next N cards (the puzzle's rule)
+    Next,
+    // Win a copy of each of the previous N cards
+    Previous,
+    // Win copies of the next N cards, dividing the number won by `divisor` at each step
+    Decay { divisor: u64 }
+}
+impl CopyRule {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name.split_once(':') {
+            Some(("decay", divisor)) => match divisor.parse::<u64>() {
+                Ok(0) => Err("The decay divisor must be at least 1".to_string()),
+                Ok(divisor) => Ok(CopyRule::Decay { divisor }),
+                Err(e) => Err(format!("Bad decay divisor {:?}: {}", divisor, e))
+            },
+            None if name == "next" => Ok(CopyRule::Next),
+            None if name == "previous" => Ok(CopyRule::Previous),
+            _ => Err(format!("Unknown copy rule {:?}", name))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OverflowPolicy {
+    Clamp,
+    Report
+}
+
+#[derive(Debug)]
+struct CascadeOverflow {
+    card_id: u32,
+    missing_cards: usize
+}
+
+struct Cascade {
+    card_ids: Vec<u32>,
+    counts: Vec<u64>,
+    caused: Vec<u64>
+}
+impl Cascade {
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    fn top_causes(&self, n: usize) -> Vec<(u32, u64)> {
+        let mut causes = self.card_ids.iter().copied().zip(self.caused.iter().copied()).collect::<Vec<_>>();
+        causes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        causes.truncate(n);
+        causes
+    }
+}
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Some(ix) = args.iter().position(|arg| arg == "--bench") {
+        let card_count = args.get(ix + 1).map_or(100_000, |count| count.parse::<usize>().unwrap());
+        bench(card_count);
+        return;
+    }
+
     let data = input_for(4).unwrap();
     let cards = parse_data(&data);
 
+    if let Some(rule_name) = args.iter().position(|arg| arg == "--cascade").and_then(|ix| args.get(ix + 1)) {
+        let rule = match CopyRule::parse(rule_name) {
+            Ok(rule) => rule,
+            Err(error) => {
+                println!("{}", error);
+                return;
+            }
+        };
+        let policy = if args.iter().any(|arg| arg == "--clamp") { OverflowPolicy::Clamp } else { OverflowPolicy::Report };
+
+        match cascade(&cards, rule, policy) {
+            Ok(result) => {
+                for (id, (count, caused)) in result.card_ids.iter().zip(result.counts.iter().zip(&result.caused)) {
+                    println!("Card {}: {} held, {} copies caused", id, count, caused);
+                }
+                println!("Total: {}", result.total());
+                for (id, caused) in result.top_causes(5) {
+                    println!("Card {} caused {} copies", id, caused);
+                }
+            },
+            Err(overflow) => println!(
+                "Card {} wins {} card(s) past the end of the table", overflow.card_id, overflow.missing_cards
+            )
+        }
+        return;
+    }
+
     let answer_one = part_one(&cards);
     println!("Part one: {}", answer_one);
     let answer_two = part_two(&cards);
     println!("Part two: {}", answer_two);
 }
 
-fn parse_data(data: &String) -> Vec<Scratchcard> {
-    data.lines().map(|line| {
-        let (name_part, numbers_part) = line.split_once(':').unwrap();
-        let id_part = name_part.split_whitespace().last().unwrap();
-        let id = id_part.parse::<u32>().unwrap();
-        let (winner_part, owned_part) = numbers_part.split_once('|').unwrap();
-
-        let winners = winner_part.split_whitespace().map(|n| n.parse::<u32>().unwrap()).collect::<HashSet<u32>>();
-        let owned = owned_part.split_whitespace().map(|n| n.parse::<u32>().unwrap()).collect::<HashSet<u32>>();
-
-        Scratchcard { id, winners, owned }
-    }).collect()
+fn parse_data(data: &str) -> Vec<Scratchcard> {
+    data.lines().map(Scratchcard::parse).collect()
 }
 
 fn part_one(cards: &[Scratchcard]) -> u32 {
     cards.iter().map(|card| {
-        let win_count = card.winners.intersection(&card.owned).count() as u32;
+        let win_count = card.matches() as u32;
         if win_count == 0 { 0 } else { 2u32.pow(win_count - 1) }
     }).sum()
 }
 
-fn part_two(cards: &[Scratchcard]) -> u32 {
-    let mut card_counts = vec![1u32; cards.len()];
-    for (ix, card) in cards.iter().enumerate() {
-        let win_count = card.winners.intersection(&card.owned).count();
+fn part_two(cards: &[Scratchcard]) -> u64 {
+    // The puzzle promises no card wins past the end of the table; if one does, ignore the extra
+    cascade(cards, CopyRule::Next, OverflowPolicy::Clamp).unwrap().total()
+}
 
-        let this_card_count = card_counts[ix];
-        for inc in 0..win_count {
-            *card_counts.get_mut(ix + inc + 1).unwrap() += this_card_count;
+fn cascade(cards: &[Scratchcard], rule: CopyRule, policy: OverflowPolicy) -> Result<Cascade, CascadeOverflow> {
+    let mut counts = vec![1u64; cards.len()];
+    let mut caused = vec![0u64; cards.len()];
+
+    // Copies only ever flow one way, so a single pass in that direction settles every count
+    let order = match rule {
+        CopyRule::Previous => (0..cards.len()).rev().collect::<Vec<usize>>(),
+        _ => (0..cards.len()).collect::<Vec<usize>>()
+    };
+    for ix in order {
+        let win_count = cards[ix].matches();
+        let (targets, missing_cards) = match rule {
+            CopyRule::Previous => (
+                (ix.saturating_sub(win_count)..ix).rev().collect::<Vec<usize>>(),
+                win_count.saturating_sub(ix)
+            ),
+            _ => (
+                (ix + 1..cards.len().min(ix + win_count + 1)).collect::<Vec<usize>>(),
+                (ix + win_count + 1).saturating_sub(cards.len())
+            )
+        };
+        if missing_cards > 0 && policy == OverflowPolicy::Report {
+            return Err(CascadeOverflow { card_id: cards[ix].id, missing_cards });
+        }
+
+        let mut won = counts[ix];
+        for target in targets {
+            counts[target] += won;
+            caused[ix] += won;
+            if let CopyRule::Decay { divisor } = rule {
+                won /= divisor;
+            }
         }
     }
 
-    card_counts.iter().sum()
-}
\ No newline at end of file
+    Ok(Cascade {
+        card_ids: cards.iter().map(|card| card.id).collect(),
+        counts,
+        caused
+    })
+}
+
+// Small LCG so synthetic decks are the same on every run
+struct DeckGenerator(u64);
+impl DeckGenerator {
+    fn next_below(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+
+    fn distinct_numbers(&mut self, count: usize) -> Vec<u64> {
+        let mut numbers = Vec::<u64>::with_capacity(count);
+        while numbers.len() < count {
+            let number = self.next_below(99) + 1;
+            if !numbers.contains(&number) {
+                numbers.push(number);
+            }
+        }
+        numbers
+    }
+
+    fn deck(&mut self, card_count: usize) -> String {
+        let mut deck = String::new();
+        for id in 1..=card_count {
+            let format_numbers = |numbers: Vec<u64>| numbers.iter().map(|n| format!("{:>2}", n)).collect::<Vec<_>>().join(" ");
+            let winners = format_numbers(self.distinct_numbers(10));
+            let owned = format_numbers(self.distinct_numbers(25));
+            deck.push_str(&format!("Card {:>6}: {} | {}\n", id, winners, owned));
+        }
+        deck
+    }
+}
+
+fn bench(card_count: usize) {
+    let deck = DeckGenerator(2023).deck(card_count);
+    println!("Generated {} cards", card_count);
+
+    let start = Instant::now();
+    let hash_cards = deck.lines().map(HashSetScratchcard::parse).collect::<Vec<_>>();
+    let hash_parsed = start.elapsed();
+    let hash_matches = hash_cards.iter().map(HashSetScratchcard::matches).sum::<usize>();
+    let hash_total = start.elapsed();
+
+    let start = Instant::now();
+    let bit_cards = parse_data(&deck);
+    let bit_parsed = start.elapsed();
+    let bit_matches = bit_cards.iter().map(Scratchcard::matches).sum::<usize>();
+    let bit_total = start.elapsed();
+
+    assert_eq!(hash_matches, bit_matches);
+    println!("HashSet: parse {:?}, match {:?}", hash_parsed, hash_total - hash_parsed);
+    println!("Bitset:  parse {:?}, match {:?}", bit_parsed, bit_total - bit_parsed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
+
+    fn total(rule: CopyRule, policy: OverflowPolicy) -> Option<u64> {
+        cascade(&parse_data(EXAMPLE), rule, policy).ok().map(|result| result.total())
+    }
+
+    #[test]
+    fn test_next() {
+        assert_eq!(total(CopyRule::Next, OverflowPolicy::Report), Some(30));
+        assert_eq!(part_two(&parse_data(EXAMPLE)), 30);
+    }
+
+    #[test]
+    fn test_previous() {
+        // Card 2 wins two cards back, but there's only card 1 before it
+        assert_eq!(total(CopyRule::Previous, OverflowPolicy::Report), None);
+        assert_eq!(total(CopyRule::Previous, OverflowPolicy::Clamp), Some(14));
+    }
+
+    #[test]
+    fn test_decay() {
+        assert_eq!(total(CopyRule::Decay { divisor: 1 }, OverflowPolicy::Report), Some(30));
+        assert_eq!(total(CopyRule::Decay { divisor: 2 }, OverflowPolicy::Report), Some(19));
+    }
+
+    #[test]
+    fn test_part_two_clamps_overflow() {
+        assert_eq!(part_two(&parse_data("Card 1: 1 2 | 1 2")), 1);
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert!(matches!(CopyRule::parse("next"), Ok(CopyRule::Next)));
+        assert!(matches!(CopyRule::parse("previous"), Ok(CopyRule::Previous)));
+        assert!(matches!(CopyRule::parse("decay:3"), Ok(CopyRule::Decay { divisor: 3 })));
+        assert!(CopyRule::parse("decay:0").is_err());
+        assert!(CopyRule::parse("decay:x").is_err());
+        assert!(CopyRule::parse("sideways").is_err());
+    }
+}
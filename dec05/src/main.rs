@@ -1,8 +1,9 @@
-use std::ops::Range;
+use std::{env, ops::Range};
 
 use input_curler::input_for;
+use intervals::IntervalSet;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct MapSegment {
     source: Range<i64>,
     difference: i64
@@ -14,34 +15,179 @@ impl MapSegment {
             difference: dest_start - source_start
         }
     }
+}
+
+fn shift(range: &Range<i64>, by: i64) -> Range<i64> {
+    (range.start + by)..(range.end + by)
+}
+
+// A map from i64 to i64 made of sorted, non-overlapping segments. Anything not
+// covered by a segment maps to itself.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct PiecewiseMap {
+    segments: Vec<MapSegment>
+}
+impl PiecewiseMap {
+    fn new(mut segments: Vec<MapSegment>) -> Self {
+        segments.retain(|segment| !segment.source.is_empty() && segment.difference != 0);
+        segments.sort_by_key(|segment| segment.source.start);
+        assert!(
+            segments.windows(2).all(|pair| pair[0].source.end <= pair[1].source.start),
+            "Map segments overlap"
+        );
+
+        let mut merged = Vec::<MapSegment>::new();
+        for segment in segments {
+            match merged.last_mut() {
+                Some(last) if last.source.end == segment.source.start && last.difference == segment.difference =>
+                    last.source.end = segment.source.end,
+                _ => merged.push(segment)
+            }
+        }
+        Self { segments: merged }
+    }
 
-    fn apply(&self, input: i64) -> Option<i64> {
-        if self.source.contains(&input) {
-            Some(input + self.difference)
-        } else {
-            None
+    fn apply(&self, input: i64) -> i64 {
+        let ix = self.segments.partition_point(|segment| segment.source.end <= input);
+        match self.segments.get(ix) {
+            Some(segment) if segment.source.contains(&input) => input + segment.difference,
+            _ => input
         }
     }
+
+    // Splits `range` into consecutive pieces, each with the difference applied to it
+    fn pieces(&self, range: Range<i64>) -> Vec<(Range<i64>, i64)> {
+        let mut pieces = vec![];
+        let mut start = range.start;
+        let first = self.segments.partition_point(|segment| segment.source.end <= start);
+        for segment in &self.segments[first..] {
+            if start >= range.end || segment.source.start >= range.end {
+                break;
+            }
+            if segment.source.start > start {
+                pieces.push((start..segment.source.start, 0));
+                start = segment.source.start;
+            }
+            let end = segment.source.end.min(range.end);
+            pieces.push((start..end, segment.difference));
+            start = end;
+        }
+        if start < range.end {
+            pieces.push((start..range.end, 0));
+        }
+        pieces
+    }
+
+    // The map that applies `self` and then `then`
+    fn compose(&self, then: &PiecewiseMap) -> PiecewiseMap {
+        let mut segments = vec![];
+        for segment in &self.segments {
+            for (image_piece, then_difference) in then.pieces(shift(&segment.source, segment.difference)) {
+                segments.push(MapSegment {
+                    source: shift(&image_piece, -segment.difference),
+                    difference: segment.difference + then_difference
+                });
+            }
+        }
+        // Where `self` is the identity, only `then` applies
+        for segment in &then.segments {
+            for (piece, self_difference) in self.pieces(segment.source.clone()) {
+                if self_difference == 0 {
+                    segments.push(MapSegment { source: piece, difference: segment.difference });
+                }
+            }
+        }
+        PiecewiseMap::new(segments)
+    }
+
+    // Only a bijection can be inverted: the segments must map their sources onto
+    // exactly the same set of values, without overlapping each other.
+    fn invert(&self) -> Option<PiecewiseMap> {
+        let mut images = self.segments.iter()
+            .map(|segment| shift(&segment.source, segment.difference))
+            .collect::<Vec<Range<i64>>>();
+        images.sort_by_key(|r| r.start);
+        if images.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return None;
+        }
+        let sources = self.segments.iter().map(|segment| segment.source.clone()).collect::<IntervalSet<i64>>();
+        if IntervalSet::normalize(images) != sources {
+            return None;
+        }
+
+        Some(PiecewiseMap::new(self.segments.iter().map(|segment| MapSegment {
+            source: shift(&segment.source, segment.difference),
+            difference: -segment.difference
+        }).collect()))
+    }
+
+    // Every input that maps into `values`, whether or not the map is invertible
+    fn preimage_of(&self, values: &IntervalSet<i64>) -> IntervalSet<i64> {
+        let sources = self.segments.iter().map(|segment| segment.source.clone()).collect::<IntervalSet<i64>>();
+        let mut preimage = values.difference(&sources);
+        for segment in &self.segments {
+            let image = IntervalSet::from(shift(&segment.source, segment.difference));
+            let hit = image.intersection(values).into_iter().map(|range| shift(&range, -segment.difference));
+            preimage = preimage.union(&hit.collect());
+        }
+        preimage
+    }
+
+    fn image_of(&self, ranges: &IntervalSet<i64>) -> IntervalSet<i64> {
+        ranges.iter()
+            .flat_map(|range| self.pieces(range.clone()))
+            .map(|(piece, difference)| shift(&piece, difference))
+            .collect()
+    }
+}
+
+// One "x-to-y map:" section of the almanac
+#[derive(Debug)]
+struct ConditionMap {
+    from: String,
+    to: String,
+    map: PiecewiseMap
 }
-type ConditionMap = Vec<MapSegment>;
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
     let data = input_for(5).unwrap();
 
     {
         let (seeds, condition_maps) = parse_data_one(&data);
+        validate_chain(&condition_maps).unwrap();
         let answer_one = part_one(&seeds, &condition_maps);
         println!("Part one: {}", answer_one);
+
+        if args.iter().any(|arg| arg == "--lineage") {
+            for &seed in &seeds {
+                let stages = lineage(seed, &condition_maps)
+                    .iter()
+                    .map(|(category, value)| format!("{} {}", category, value))
+                    .collect::<Vec<String>>();
+                println!("{}", stages.join(", "));
+            }
+        }
     }
 
     {
         let (seeds, condition_maps) = parse_data_two(&data);
         let answer_two = part_two(&seeds, &condition_maps);
         println!("Part two: {}", answer_two);
+        if let Some(location_to_seed) = seed_to_location(condition_maps.iter().map(|condition_map| &condition_map.map)).invert() {
+            println!("  (from seed {})", location_to_seed.apply(answer_two));
+        }
+
+        if let Some(location) = args.iter().position(|arg| arg == "--reverse").and_then(|ix| args.get(ix + 1)) {
+            let location = location.parse::<i64>().unwrap();
+            let reaching = seeds_reaching(location, &condition_maps);
+            println!("Seeds reaching location {}: {:?}", location, reaching.ranges());
+            println!("  of which planted: {:?}", reaching.intersection(&seeds).ranges());
+        }
     }
 }
 
-fn parse_data_one(data: &String) -> (Vec<i64>, Vec<ConditionMap>) {
+fn parse_data_one(data: &str) -> (Vec<i64>, Vec<ConditionMap>) {
     let mut lines = data.lines().filter(|l| !l.is_empty());
 
     let seeds_line = lines.next().unwrap();
@@ -55,7 +201,7 @@ fn parse_data_one(data: &String) -> (Vec<i64>, Vec<ConditionMap>) {
     (seeds, condition_maps)
 }
 
-fn parse_data_two(data: &String) -> (Vec<Range<i64>>, Vec<ConditionMap>) {
+fn parse_data_two(data: &str) -> (IntervalSet<i64>, Vec<ConditionMap>) {
     let mut lines = data.lines().filter(|l| !l.is_empty());
 
     let seeds_line = lines.next().unwrap();
@@ -66,7 +212,7 @@ fn parse_data_two(data: &String) -> (Vec<Range<i64>>, Vec<ConditionMap>) {
         .collect::<Vec<i64>>()
         .chunks(2)
         .map(|pair| pair[0]..(pair[0]+pair[1]))
-        .collect::<Vec<Range<i64>>>();
+        .collect::<IntervalSet<i64>>();
     let condition_maps = parse_condition_maps(&mut lines);
 
     (seeds, condition_maps)
@@ -74,84 +220,259 @@ fn parse_data_two(data: &String) -> (Vec<Range<i64>>, Vec<ConditionMap>) {
 
 fn parse_condition_maps(lines: &mut dyn Iterator<Item = &str>) -> Vec<ConditionMap> {
     let mut condition_maps = Vec::<ConditionMap>::new();
-    let mut current_map = ConditionMap::new();
-    let mut first = true;
+    let mut current_categories = None;
+    let mut current_map = Vec::<MapSegment>::new();
     for line in lines {
-        if line.ends_with("map:") {
-            if !first { condition_maps.push(current_map) }
-            current_map = ConditionMap::new();
-            first = false;
+        if let Some(header) = line.strip_suffix(" map:") {
+            if let Some((from, to)) = current_categories.take() {
+                condition_maps.push(ConditionMap { from, to, map: PiecewiseMap::new(current_map) });
+            }
+            let (from, to) = header.split_once("-to-").unwrap();
+            current_categories = Some((from.to_string(), to.to_string()));
+            current_map = vec![];
             continue;
         }
 
         let mut numbers = line.split_whitespace().map(|s| s.parse::<i64>().unwrap());
         current_map.push(MapSegment::new(numbers.next().unwrap(), numbers.next().unwrap(), numbers.next().unwrap()));
     }
-    condition_maps.push(current_map);
+    let (from, to) = current_categories.unwrap();
+    condition_maps.push(ConditionMap { from, to, map: PiecewiseMap::new(current_map) });
 
     condition_maps
 }
 
+// Checks the maps lead from seed to location, each one picking up where the last left off
+fn validate_chain(condition_maps: &[ConditionMap]) -> Result<(), String> {
+    let mut category = "seed";
+    for condition_map in condition_maps {
+        if condition_map.from != category {
+            return Err(format!("{}-to-{} map follows a map to {}", condition_map.from, condition_map.to, category));
+        }
+        category = &condition_map.to;
+    }
+    if category != "location" {
+        return Err(format!("Maps end at {}, not location", category));
+    }
+    Ok(())
+}
+
+fn lineage(seed: i64, condition_maps: &[ConditionMap]) -> Vec<(&str, i64)> {
+    let mut stages = vec![("seed", seed)];
+    for condition_map in condition_maps {
+        let (_, value) = *stages.last().unwrap();
+        stages.push((&condition_map.to, condition_map.map.apply(value)));
+    }
+    stages
+}
+
+fn seeds_reaching(location: i64, condition_maps: &[ConditionMap]) -> IntervalSet<i64> {
+    condition_maps.iter().rev().fold(
+        IntervalSet::from(location..location + 1),
+        |values, condition_map| condition_map.map.preimage_of(&values)
+    )
+}
+
+fn seed_to_location<'a>(condition_maps: impl IntoIterator<Item = &'a PiecewiseMap>) -> PiecewiseMap {
+    condition_maps.into_iter().fold(PiecewiseMap::default(), |map, next| map.compose(next))
+}
+
 fn part_one(seeds: &[i64], condition_maps: &[ConditionMap]) -> i64 {
-    let locations = seeds.iter().map(|seed| {
-        let mut output = *seed;
+    let seed_to_location = seed_to_location(condition_maps.iter().map(|condition_map| &condition_map.map));
+    seeds.iter().map(|&seed| seed_to_location.apply(seed)).min().unwrap()
+}
 
-        for condition_map in condition_maps {
-            output = condition_map
-                .iter()
-                .filter_map(|segment| segment.apply(output))
-                .next()
-                .unwrap_or(output);
+fn part_two(seeds: &IntervalSet<i64>, condition_maps: &[ConditionMap]) -> i64 {
+    let locations = seed_to_location(condition_maps.iter().map(|condition_map| &condition_map.map)).image_of(seeds);
+    locations.min().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic pseudo-random numbers so failures can be reproduced
+    struct Lcg(u64);
+    impl Lcg {
+        fn below(&mut self, bound: i64) -> i64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % bound as u64) as i64
         }
 
-        output
-    }).collect::<Vec<i64>>();
-    *locations.iter().min().unwrap()
-}
+        fn map(&mut self) -> Vec<MapSegment> {
+            let mut segments = vec![];
+            let mut start = self.below(5);
+            for _ in 0..self.below(4) {
+                let len = self.below(8) + 1;
+                segments.push(MapSegment::new(self.below(40), start, len));
+                start += len + self.below(4);
+            }
+            segments
+        }
+
+        // Swaps adjacent blocks, so the result is always a bijection
+        fn permutation(&mut self) -> Vec<MapSegment> {
+            let mut segments = vec![];
+            let mut start = self.below(5);
+            for _ in 0..self.below(3) {
+                let (first_len, second_len) = (self.below(6) + 1, self.below(6) + 1);
+                segments.push(MapSegment::new(start + second_len, start, first_len));
+                segments.push(MapSegment::new(start, start + first_len, second_len));
+                start += first_len + second_len + self.below(4);
+            }
+            segments
+        }
+    }
 
-fn part_two(seeds: &[Range<i64>], condition_maps: &[ConditionMap]) -> i64 {
-    let mut working_copy = seeds.iter().map(|r| r.clone()).collect::<Vec<Range<i64>>>();
-    let mut next_ranges = Vec::<Range<i64>>::new();
+    fn brute_apply(maps: &[Vec<MapSegment>], input: i64) -> i64 {
+        maps.iter().fold(input, |value, map| map
+            .iter()
+            .find(|segment| segment.source.contains(&value))
+            .map_or(value, |segment| value + segment.difference))
+    }
 
-    for condition_map in condition_maps {
-        for range in &working_copy {
-            let mut maybe_working_range = Some(range.clone());
+    #[test]
+    fn test_compose_matches_brute_force() {
+        let mut rng = Lcg(5);
+        for _ in 0..200 {
+            let raw_maps = (0..3).map(|_| rng.map()).collect::<Vec<_>>();
+            let composed = seed_to_location(&raw_maps.iter().cloned().map(PiecewiseMap::new).collect::<Vec<_>>());
+            for input in -10..80 {
+                assert_eq!(composed.apply(input), brute_apply(&raw_maps, input));
+            }
+        }
+    }
 
-            while let Some(ref working_range) = maybe_working_range {
-                let maybe_segment = condition_map
-                    .iter()
-                    .find(|segment| segment.apply(working_range.start).is_some());
-
-                if let Some(segment) = maybe_segment {
-                    // Need to find the last entry in the range that is handled by the segment
-                    if segment.source.end >= working_range.end {
-                        // All covered by the segment
-                        next_ranges.push((working_range.start + segment.difference)..(working_range.end + segment.difference));
-                        maybe_working_range = None;
-                    } else {
-                        next_ranges.push((working_range.start + segment.difference)..(segment.source.end + segment.difference));
-                        maybe_working_range = Some(segment.source.end..working_range.end);
-                    }
-                } else {
-                    // Need to find the first entry in the range that is actually mapped
-                    let maybe_subrange_start = condition_map
-                        .iter()
-                        .find(|segment| working_range.contains(&segment.source.start))
-                        .map(|segment| segment.source.start);
-
-                    if let Some(subrange_start) = maybe_subrange_start {
-                        next_ranges.push(working_range.start..subrange_start);
-                        maybe_working_range = Some(subrange_start..working_range.end);
-                    } else {
-                        next_ranges.push(working_range.clone());
-                        maybe_working_range = None;
-                    }
-                }
+    #[test]
+    fn test_invert_undoes_permutation() {
+        let mut rng = Lcg(7);
+        for _ in 0..200 {
+            let map = PiecewiseMap::new(rng.permutation());
+            let inverse = map.invert().unwrap();
+            for input in -10..80 {
+                assert_eq!(inverse.apply(map.apply(input)), input);
+            }
+        }
+
+        assert_eq!(PiecewiseMap::new(vec![MapSegment::new(10, 0, 5)]).invert(), None);
+    }
+
+    #[test]
+    fn test_image_matches_brute_force() {
+        let mut rng = Lcg(11);
+        for _ in 0..200 {
+            let raw_maps = (0..2).map(|_| rng.map()).collect::<Vec<_>>();
+            let composed = seed_to_location(&raw_maps.iter().cloned().map(PiecewiseMap::new).collect::<Vec<_>>());
+            let start = rng.below(30);
+            let ranges = IntervalSet::from_iter([start..start + rng.below(20), rng.below(10)..rng.below(10) + 10]);
+
+            let mut expected = ranges.iter().flat_map(|r| r.clone()).map(|input| brute_apply(&raw_maps, input)).collect::<Vec<_>>();
+            expected.sort();
+            expected.dedup();
+            let actual = composed.image_of(&ranges).into_iter().flatten().collect::<Vec<_>>();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_preimage_matches_brute_force() {
+        let mut rng = Lcg(13);
+        for _ in 0..200 {
+            let raw_map = rng.map();
+            let map = PiecewiseMap::new(raw_map.clone());
+            let start = rng.below(50);
+            let values = IntervalSet::from_iter([start..start + rng.below(15), rng.below(40)..rng.below(40) + 5]);
+
+            let preimage = map.preimage_of(&values);
+            // Segments shift values by less than 60 either way, so nothing outside this window can land in `values`
+            let window = -100..150;
+            assert!(preimage.iter().all(|range| range.start >= window.start && range.end <= window.end));
+            for input in window {
+                let output = brute_apply(std::slice::from_ref(&raw_map), input);
+                assert_eq!(preimage.contains(&input), values.contains(&output), "input {}", input);
+            }
+        }
+    }
+
+    fn condition_map(from: &str, to: &str, segments: Vec<MapSegment>) -> ConditionMap {
+        ConditionMap { from: from.to_string(), to: to.to_string(), map: PiecewiseMap::new(segments) }
+    }
+
+    #[test]
+    fn test_seeds_reaching_matches_brute_force() {
+        let mut rng = Lcg(17);
+        for _ in 0..100 {
+            let raw_maps = (0..3).map(|_| rng.map()).collect::<Vec<_>>();
+            let condition_maps = vec![
+                condition_map("seed", "soil", raw_maps[0].clone()),
+                condition_map("soil", "water", raw_maps[1].clone()),
+                condition_map("water", "location", raw_maps[2].clone())
+            ];
+            let location = rng.below(60);
+            let seeds = seeds_reaching(location, &condition_maps);
+            for seed in -150..200 {
+                assert_eq!(seeds.contains(&seed), brute_apply(&raw_maps, seed) == location, "seed {}", seed);
             }
         }
-        working_copy = next_ranges;
-        next_ranges = vec![];
     }
 
-    working_copy.iter().map(|r| r.start).min().unwrap()
-}
\ No newline at end of file
+    #[test]
+    fn test_validate_chain() {
+        let ordered = vec![
+            condition_map("seed", "soil", vec![]),
+            condition_map("soil", "location", vec![])
+        ];
+        assert_eq!(validate_chain(&ordered), Ok(()));
+
+        let misordered = vec![
+            condition_map("soil", "location", vec![]),
+            condition_map("seed", "soil", vec![])
+        ];
+        assert!(validate_chain(&misordered).is_err());
+
+        let unfinished = vec![condition_map("seed", "soil", vec![])];
+        assert!(validate_chain(&unfinished).is_err());
+    }
+
+    #[test]
+    fn test_example() {
+        let data = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4";
+        let (seeds, condition_maps) = parse_data_one(data);
+        validate_chain(&condition_maps).unwrap();
+        assert_eq!(part_one(&seeds, &condition_maps), 35);
+        let (seeds, condition_maps) = parse_data_two(data);
+        assert_eq!(part_two(&seeds, &condition_maps), 46);
+    }
+}
@@ -1,50 +1,324 @@
+use std::{env, ops::RangeInclusive, str::FromStr, fmt::Debug};
+
 use input_curler::input_for;
 use itertools::Itertools;
+use num::{integer::Roots, BigUint, Integer};
+
+struct Race<N> {
+    time: N,
+    record: N
+}
+
+// How far a boat goes once released, given how long its button was held
+trait BoatModel {
+    fn distance(&self, hold: u64, time: u64) -> u64;
+
+    // The distance as a polynomial in the hold time, if it is one; those races are solved analytically
+    fn polynomial(&self, _time: u64) -> Option<Polynomial> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Polynomial {
+    // factor * hold * (time - hold)
+    Quadratic(u64),
+    // factor * hold^2 * (time - hold)
+    Cubic(u64)
+}
+
+// Each millisecond held adds `acceleration` to the speed. The puzzle's boats have an acceleration of 1.
+struct Linear {
+    acceleration: u64
+}
+impl BoatModel for Linear {
+    fn distance(&self, hold: u64, time: u64) -> u64 {
+        self.acceleration * hold * (time - hold)
+    }
+
+    fn polynomial(&self, _time: u64) -> Option<Polynomial> {
+        Some(Polynomial::Quadratic(self.acceleration))
+    }
+}
+
+struct Capped {
+    acceleration: u64,
+    max_speed: u64
+}
+impl BoatModel for Capped {
+    fn distance(&self, hold: u64, time: u64) -> u64 {
+        (self.acceleration * hold).min(self.max_speed) * (time - hold)
+    }
+
+    fn polynomial(&self, time: u64) -> Option<Polynomial> {
+        // A cap the boat can never reach makes no difference
+        (self.acceleration * time <= self.max_speed).then_some(Polynomial::Quadratic(self.acceleration))
+    }
+}
+
+// The speed grows with the square of the hold time
+struct SquareCharge {
+    factor: u64
+}
+impl BoatModel for SquareCharge {
+    fn distance(&self, hold: u64, time: u64) -> u64 {
+        self.factor * hold * hold * (time - hold)
+    }
+
+    fn polynomial(&self, _time: u64) -> Option<Polynomial> {
+        Some(Polynomial::Cubic(self.factor))
+    }
+}
+
+// Any speed curve, as long as the distance it gives rises to a single peak and then falls
+struct ChargeCurve {
+    speed: fn(u64) -> u64
+}
+impl BoatModel for ChargeCurve {
+    fn distance(&self, hold: u64, time: u64) -> u64 {
+        (self.speed)(hold).saturating_mul(time - hold)
+    }
+}
 
-struct Race {
-    time: u64,
-    record: u64
+#[derive(Debug, PartialEq, Eq)]
+struct RaceOutcome {
+    winning_holds: Option<RangeInclusive<u64>>,
+    optimal_hold: u64
 }
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
     let data = input_for(6).unwrap();
 // let data = "Time:      7  15   30
 // Distance:  9  40  200".to_string();
 
-    let races = parse_data(&data, false);
+    if let Some(model_name) = args.iter().position(|arg| arg == "--model").and_then(|ix| args.get(ix + 1)) {
+        let params = model_name.split(':').skip(1).map(|p| p.parse::<u64>().unwrap()).collect::<Vec<u64>>();
+        let model: Box<dyn BoatModel> = match model_name.split(':').next().unwrap() {
+            "linear" => Box::new(Linear { acceleration: params[0] }),
+            "capped" => Box::new(Capped { acceleration: params[0], max_speed: params[1] }),
+            "square" => Box::new(SquareCharge { factor: params.first().copied().unwrap_or(1) }),
+            // Speed doubles with every millisecond held, which no polynomial keeps up with
+            "doubling" => Box::new(ChargeCurve { speed: |hold| 2u64.saturating_pow(hold.min(64) as u32) }),
+            unknown => panic!("Unknown boat model {}", unknown)
+        };
+
+        let races = parse_data::<u64>(&data, false);
+        for race in &races {
+            let outcome = race_outcome(race, model.as_ref());
+            println!("Race of {}ms: win by holding {:?}, best is {}ms", race.time, outcome.winning_holds, outcome.optimal_hold);
+        }
+        return;
+    }
+
+    let races = parse_data::<u64>(&data, false);
     let answer_one = part_one(&races);
     println!("Part one: {}", answer_one);
 
-    let races = parse_data(&data, true);
+    // Kerning can make the race arbitrarily long, so don't trust it to fit in a machine word
+    let races = parse_data::<BigUint>(&data, true);
     let answer_two = part_one(&races);
     println!("Part two: {}", answer_two);
 }
 
-fn parse_data(data: &str, combine: bool) -> Vec<Race> {
+fn parse_data<N: FromStr>(data: &str, combine: bool) -> Vec<Race<N>> where N::Err: Debug {
     let mut times_part = data.lines().next().unwrap().split_whitespace().skip(1);
     let mut records_part = data.lines().nth(1).unwrap().split_whitespace().skip(1);
 
     if combine {
         vec![
             Race {
-                time: times_part.join("").parse::<u64>().unwrap(),
-                record: records_part.join("").parse::<u64>().unwrap()
+                time: times_part.join("").parse::<N>().unwrap(),
+                record: records_part.join("").parse::<N>().unwrap()
             }
         ]
     } else {
-        let times = times_part.map(|t| t.parse::<u64>().unwrap());
-        let records = records_part.map(|t| t.parse::<u64>().unwrap());
+        let times = times_part.map(|t| t.parse::<N>().unwrap());
+        let records = records_part.map(|t| t.parse::<N>().unwrap());
 
         times.zip(records).map(|(time, record)| Race { time, record } ).collect()
     }
 
 }
 
-fn part_one(races: &[Race]) -> u64 {
-    races.iter().map(|race| {
-        let min_hold = (0..race.time).find(|hold_time|
-            hold_time * (race.time - hold_time) > race.record
-        ).unwrap();
-        race.time + 1 - min_hold * 2
-    }).product()
-}
\ No newline at end of file
+// Holding for h goes h * (time - h), so the winning holds lie strictly between the roots
+// of h^2 - time.h + record. Works entirely in integers, so there's no rounding to go wrong.
+fn winning_holds<N: Integer + Roots + Clone>(race: &Race<N>) -> Option<RangeInclusive<N>> {
+    let two = N::one() + N::one();
+    let four = two.clone() * two.clone();
+    let wins = |hold: &N| hold.clone() * (race.time.clone() - hold.clone()) > race.record;
+
+    let time_squared = race.time.clone() * race.time.clone();
+    let four_record = four * race.record.clone();
+    if time_squared <= four_record {
+        return None;
+    }
+    let root = (time_squared - four_record).sqrt();
+
+    // The floored root can leave the estimate a step either side of the true boundary
+    let mut min_hold = (race.time.clone() - root.min(race.time.clone())) / two.clone();
+    while min_hold > N::zero() && wins(&(min_hold.clone() - N::one())) {
+        min_hold = min_hold - N::one();
+    }
+    while !wins(&min_hold) {
+        min_hold = min_hold + N::one();
+        if min_hold.clone() * two.clone() > race.time {
+            return None;
+        }
+    }
+
+    let max_hold = race.time.clone() - min_hold.clone();
+    Some(min_hold..=max_hold)
+}
+
+fn part_one<N: Integer + Roots + Clone>(races: &[Race<N>]) -> N {
+    races.iter().map(|race|
+        winning_holds(race).map_or(N::zero(), |holds| holds.end().clone() + N::one() - holds.start().clone())
+    ).fold(N::one(), |product, ways| product * ways)
+}
+
+fn race_outcome(race: &Race<u64>, model: &dyn BoatModel) -> RaceOutcome {
+    match model.polynomial(race.time) {
+        // A boat that never moves never wins
+        Some(Polynomial::Quadratic(0) | Polynomial::Cubic(0)) => RaceOutcome { winning_holds: None, optimal_hold: 0 },
+        Some(Polynomial::Quadratic(factor)) => {
+            // factor * x > record exactly when x > record / factor, for whole x
+            let scaled = Race { time: race.time, record: race.record / factor };
+            RaceOutcome { winning_holds: winning_holds(&scaled), optimal_hold: race.time / 2 }
+        },
+        Some(Polynomial::Cubic(factor)) => cubic_outcome(race, factor),
+        None => searched_outcome(race, model)
+    }
+}
+
+// factor * h^2 * (time - h) peaks at h = 2 * time / 3, and crosses the record at the roots of
+// h^3 - time.h^2 + record / factor, which has three real roots whenever the peak beats the record:
+// one negative and the two that bound the winning holds. The roots come from the trigonometric
+// solution, and the integer boundaries are then checked exactly, as floats can be a step out.
+fn cubic_outcome(race: &Race<u64>, factor: u64) -> RaceOutcome {
+    let (time, record) = (race.time as u128, race.record as u128);
+    let distance = |hold: u128| factor as u128 * hold * hold * (time - hold);
+    let wins = |hold: u128| distance(hold) > record;
+
+    let peak = 2 * time / 3;
+    let optimal_hold = if peak < time && distance(peak + 1) > distance(peak) { peak + 1 } else { peak };
+    if !wins(optimal_hold) {
+        return RaceOutcome { winning_holds: None, optimal_hold: optimal_hold as u64 };
+    }
+
+    // Substituting h = t + time / 3 gives t^3 + p.t + q = 0
+    let time_f = race.time as f64;
+    let p = -time_f * time_f / 3.0;
+    let q = -2.0 * time_f.powi(3) / 27.0 + race.record as f64 / factor as f64;
+    let radius = 2.0 * (-p / 3.0).sqrt();
+    let angle = ((3.0 * q / (2.0 * p)) * (-3.0 / p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+    let root = |k: f64| radius * (angle - 2.0 * std::f64::consts::PI * k / 3.0).cos() + time_f / 3.0;
+
+    let mut first_win = (root(1.0).max(0.0) as u128).min(optimal_hold);
+    while first_win > 0 && wins(first_win - 1) {
+        first_win -= 1;
+    }
+    while !wins(first_win) {
+        first_win += 1;
+    }
+    let mut last_win = (root(0.0).max(0.0) as u128).clamp(optimal_hold, time);
+    while last_win < time && wins(last_win + 1) {
+        last_win += 1;
+    }
+    while !wins(last_win) {
+        last_win -= 1;
+    }
+    RaceOutcome { winning_holds: Some(first_win as u64..=last_win as u64), optimal_hold: optimal_hold as u64 }
+}
+
+// Assumes the distance rises to a single peak and then falls, and searches either side of it
+fn searched_outcome(race: &Race<u64>, model: &dyn BoatModel) -> RaceOutcome {
+    let distance = |hold: u64| model.distance(hold, race.time);
+    let optimal_hold = partition_point(0, race.time, |hold| distance(hold) < distance(hold + 1));
+    if distance(optimal_hold) <= race.record {
+        return RaceOutcome { winning_holds: None, optimal_hold };
+    }
+
+    let first_win = partition_point(0, optimal_hold, |hold| distance(hold) <= race.record);
+    let last_win = partition_point(optimal_hold, race.time + 1, |hold| distance(hold) > race.record) - 1;
+    RaceOutcome { winning_holds: Some(first_win..=last_win), optimal_hold }
+}
+
+// The first value in low..high for which `pred` is false, where `pred` is true and then false across it
+fn partition_point(mut low: u64, mut high: u64, pred: impl Fn(u64) -> bool) -> u64 {
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if pred(mid) { low = mid + 1 } else { high = mid }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force(time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        let mut holds = (0..=time).filter(|hold_time| hold_time * (time - hold_time) > record);
+        let first = holds.next()?;
+        Some(first..=holds.next_back().unwrap_or(first))
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        for time in 0..120u64 {
+            for record in 0..(time * time / 4 + 3) {
+                assert_eq!(winning_holds(&Race { time, record }), brute_force(time, record), "time {}, record {}", time, record);
+            }
+        }
+    }
+
+    #[test]
+    fn test_widths_agree() {
+        let data = "Time:      7  15   30\nDistance:  9  40  200";
+        assert_eq!(part_one(&parse_data::<u64>(data, false)), 288);
+        assert_eq!(part_one(&parse_data::<u64>(data, true)), 71503);
+        assert_eq!(part_one(&parse_data::<u128>(data, true)), 71503);
+        assert_eq!(part_one(&parse_data::<BigUint>(data, true)), BigUint::from(71503u32));
+
+        let huge = "Time: 123456789012345678901234567890\nDistance: 1234567890123456789012345678901234567890";
+        let holds = winning_holds(&parse_data::<BigUint>(huge, false)[0]).unwrap();
+        let time = BigUint::from_str("123456789012345678901234567890").unwrap();
+        let record = BigUint::from_str("1234567890123456789012345678901234567890").unwrap();
+        let lowest = holds.start().clone();
+        assert!(lowest.clone() * (time.clone() - lowest.clone()) > record);
+        let below = lowest - 1u32;
+        assert!(below.clone() * (time - below) <= record);
+    }
+
+    // Wins are whatever beats the record, and the optimal hold is the first that goes furthest
+    fn brute_force_outcome(race: &Race<u64>, model: &dyn BoatModel) -> RaceOutcome {
+        let distances = (0..=race.time).map(|hold| model.distance(hold, race.time)).collect::<Vec<u64>>();
+        let best = *distances.iter().max().unwrap();
+        let optimal_hold = distances.iter().position(|&distance| distance == best).unwrap() as u64;
+        let mut wins = (0..=race.time).filter(|&hold| distances[hold as usize] > race.record);
+        let winning_holds = wins.next().map(|first| first..=wins.next_back().unwrap_or(first));
+        RaceOutcome { winning_holds, optimal_hold }
+    }
+
+    fn assert_matches_brute_force(model: &dyn BoatModel, max_time: u64) {
+        for time in 0..max_time {
+            let peak = (0..=time).map(|hold| model.distance(hold, time)).max().unwrap();
+            for record in (0..peak + 3).step_by(1 + peak as usize / 200) {
+                let race = Race { time, record };
+                assert_eq!(race_outcome(&race, model), brute_force_outcome(&race, model), "time {}, record {}", time, record);
+            }
+        }
+    }
+
+    #[test]
+    fn test_models_match_brute_force() {
+        for acceleration in 0..4 {
+            assert_matches_brute_force(&Linear { acceleration }, 60);
+            assert_matches_brute_force(&Capped { acceleration, max_speed: 0 }, 40);
+            assert_matches_brute_force(&Capped { acceleration, max_speed: 25 }, 60);
+            assert_matches_brute_force(&SquareCharge { factor: acceleration }, 60);
+        }
+        assert_matches_brute_force(&ChargeCurve { speed: |hold| 2u64.saturating_pow(hold.min(64) as u32) }, 60);
+        assert_matches_brute_force(&ChargeCurve { speed: |hold| hold.min(10) * hold }, 60);
+    }
+}
@@ -1,50 +1,122 @@
-use std::collections::HashMap;
+use std::env;
 
 use input_curler::input_for;
 use itertools::Itertools;
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Hash)]
 enum HandType {
     HighCard,
     OnePair,
     TwoPair,
     ThreeKind,
+    Straight,
     FullHouse,
     FourKind,
     FiveKind
 }
 use HandType::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TieBreak {
+    // Compare the cards one at a time, in the order they were dealt
+    InOrder,
+    // Compare the biggest group first, then the next, as poker does
+    ByGroup
+}
+
+#[derive(Debug, Clone)]
+struct Ruleset {
+    // Every card, weakest first
+    card_order: Vec<char>,
+    wild_cards: Vec<char>,
+    // The hand types that can be made, weakest first. A wild card is always counted towards the
+    // biggest group, so more-of-a-kind must beat less-of-a-kind.
+    type_precedence: Vec<HandType>,
+    tie_break: TieBreak
+}
+impl Ruleset {
+    fn standard() -> Self {
+        Self {
+            card_order: "23456789TJQKA".chars().collect(),
+            wild_cards: vec![],
+            type_precedence: vec![HighCard, OnePair, TwoPair, ThreeKind, FullHouse, FourKind, FiveKind],
+            tie_break: TieBreak::InOrder
+        }
+    }
+
+    fn jokers_wild() -> Self {
+        Self {
+            card_order: "J23456789TQKA".chars().collect(),
+            wild_cards: vec!['J'],
+            ..Self::standard()
+        }
+    }
+
+    fn with_wild(mut self, card: char) -> Self {
+        self.wild_cards.push(card);
+        self
+    }
+
+    // Five consecutive cards (ignoring suits, as Camel Cards has none) make a straight, ranked as in poker.
+    // The top card may also play below the bottom one, so A2345 is the wheel, the lowest straight.
+    fn poker_straights() -> Self {
+        Self {
+            type_precedence: vec![HighCard, OnePair, TwoPair, ThreeKind, Straight, FullHouse, FourKind, FiveKind],
+            tie_break: TieBreak::ByGroup,
+            ..Self::standard()
+        }
+    }
+
+    fn card_value(&self, card: char) -> Option<u8> {
+        self.card_order.iter().position(|&c| c == card).map(|value| value as u8)
+    }
+
+    fn is_wild(&self, value: u8) -> bool {
+        self.wild_cards.contains(&self.card_order[value as usize])
+    }
+
+    fn type_rank(&self, hand_type: HandType) -> Option<usize> {
+        self.type_precedence.iter().position(|&t| t == hand_type)
+    }
+}
+
 #[derive(Debug)]
 struct Hand {
-    cards: [u8; 5],
     bid: u32,
-    classification: HandType
+    classification: HandType,
+    // Sorts hands from weakest to strongest
+    strength: (usize, [u8; 5])
 }
 impl Hand {
-    fn new(card_str: &str, bid: u32, part: u8) -> Self {
-        let cards: [u8; 5] = card_str.chars().map(|c|
-            match c {
-                'A' => 14,
-                'K' => 13,
-                'Q' => 12,
-                'J' => if part == 1 { 11 } else { 1 },
-                'T' => 10,
-                _ => c.to_digit(10).unwrap()
-            } as u8
-        ).collect::<Vec<u8>>().try_into().unwrap();
-        let classification = Self::classify(&cards);
+    fn new(card_str: &str, bid: u32, ruleset: &Ruleset) -> Self {
+        let cards: [u8; 5] = card_str.chars()
+            .map(|c| ruleset.card_value(c).unwrap_or_else(|| panic!("Unknown card {}", c)))
+            .collect::<Vec<u8>>()
+            .try_into()
+            .unwrap();
+        Self::from_cards(cards, bid, ruleset)
+    }
+
+    fn from_cards(cards: [u8; 5], bid: u32, ruleset: &Ruleset) -> Self {
+        let classification = Self::classify(&cards, ruleset);
+        let strength = (ruleset.type_rank(classification).unwrap(), Self::tie_break(&cards, classification, ruleset));
 
         Self {
-            cards,
             bid,
-            classification
+            classification,
+            strength
         }
     }
 
-    fn classify(cards: &[u8; 5]) -> HandType {
-        let most_matching = Self::most_matching(cards);
-        match most_matching {
+    fn classify(cards: &[u8; 5], ruleset: &Ruleset) -> HandType {
+        let (wild, natural): (Vec<u8>, Vec<u8>) = cards.iter().partition(|&&card| ruleset.is_wild(card));
+
+        let mut counts = natural.iter().counts().into_values().sorted().rev().collect::<Vec<usize>>();
+        if counts.is_empty() {
+            counts.push(0);
+        }
+        counts[0] += wild.len();
+        let by_groups = match (counts[0], counts.get(1).copied().unwrap_or(0)) {
             (5, 0) => FiveKind,
             (4, 1) => FourKind,
             (3, 2) => FullHouse,
@@ -53,52 +125,282 @@ impl Hand {
             (2, 1) => OnePair,
             (1, 1) => HighCard,
             _ => unreachable!()
-        }
+        };
+
+        // Wild cards can fill any gaps, as long as the natural cards fit within five in a row
+        let straight = ruleset.card_order.len() >= 5 &&
+            (Self::fits_straight(&natural, false, ruleset) || Self::fits_straight(&natural, true, ruleset));
+
+        [Some(by_groups), straight.then_some(Straight)]
+            .into_iter()
+            .flatten()
+            .filter_map(|hand_type| ruleset.type_rank(hand_type).map(|rank| (rank, hand_type)))
+            .max()
+            .unwrap()
+            .1
     }
 
-    fn most_matching(cards: &[u8; 5]) -> (usize, usize) {
-        let mut counts = cards.iter()
-            .fold(HashMap::<u8, usize>::new(), |mut acc, c| {
-                acc.entry(*c).and_modify(|cnt| *cnt += 1).or_insert(1);
-                acc
-            });
+    fn fits_straight(natural: &[u8], top_plays_low: bool, ruleset: &Ruleset) -> bool {
+        let top = ruleset.card_order.len() as i16 - 1;
+        let values = natural.iter()
+            .map(|&card| if top_plays_low && card as i16 == top { -1 } else { card as i16 })
+            .collect::<Vec<i16>>();
+        values.iter().all_unique() && values.iter().minmax().into_option().is_none_or(|(low, high)| high - low < 5)
+    }
 
-        let jokers = *counts.get(&1).unwrap_or(&0);
-        let maybe_most = counts.iter_mut().filter(|(&val, _)| val != 1).max_by_key(|(_, num)| **num);
-        if let Some(most) = maybe_most {
-            *most.1 += jokers;
-            counts.remove(&1);
+    fn tie_break(cards: &[u8; 5], classification: HandType, ruleset: &Ruleset) -> [u8; 5] {
+        match ruleset.tie_break {
+            TieBreak::InOrder => *cards,
+            TieBreak::ByGroup => {
+                let counts = cards.iter().counts();
+                let mut sorted = *cards;
+                sorted.sort_by_key(|card| std::cmp::Reverse((counts[card], *card)));
+                // In the wheel the top card plays low, so it is compared last
+                let natural = cards.iter().copied().filter(|&card| !ruleset.is_wild(card)).collect::<Vec<u8>>();
+                if classification == Straight && !Self::fits_straight(&natural, false, ruleset) {
+                    sorted.rotate_left(1);
+                }
+                sorted
+            }
         }
-        counts.into_values()
-            .sorted()
-            .rev()
-            .next_tuple()
-            .unwrap_or((5, 0))
     }
 }
 
+// Every possible five-card string under a ruleset, weakest first
+struct StrengthTable {
+    hands: Vec<([u8; 5], Hand)>
+}
+impl StrengthTable {
+    fn build(ruleset: &Ruleset) -> Self {
+        let card_count = ruleset.card_order.len() as u8;
+        let mut hands = (0..5)
+            .map(|_| 0..card_count)
+            .multi_cartesian_product()
+            .map(|cards| {
+                let cards: [u8; 5] = cards.try_into().unwrap();
+                (cards, Hand::from_cards(cards, 0, ruleset))
+            })
+            .collect::<Vec<_>>();
+        hands.sort_by_key(|(_, hand)| hand.strength);
+        Self { hands }
+    }
+
+    // How many hands are strictly weaker than this one
+    fn rank_of(&self, hand: &Hand) -> usize {
+        self.hands.partition_point(|(_, other)| other.strength < hand.strength)
+    }
+
+    fn type_counts(&self) -> Vec<(HandType, usize)> {
+        self.hands.iter().map(|(_, hand)| hand.classification).counts().into_iter().sorted().collect()
+    }
+}
+
+// The chance of finishing with each hand type, given the cards already held and the
+// cards left to draw from. Every way of drawing the rest of the hand is equally likely.
+fn equity(partial: &str, deck: &str, ruleset: &Ruleset) -> Result<Vec<(HandType, f64)>, String> {
+    let values = |cards: &str| cards.chars()
+        .map(|c| ruleset.card_value(c).ok_or_else(|| format!("Unknown card {:?}", c)))
+        .collect::<Result<Vec<u8>, String>>();
+    let held = values(partial)?;
+    let deck = values(deck)?;
+    if held.len() > 5 {
+        return Err(format!("A hand holds five cards, not {}", held.len()));
+    }
+    if held.len() + deck.len() < 5 {
+        return Err(format!("The deck has {} card(s), too few to finish the hand", deck.len()));
+    }
+
+    let outcomes = deck.iter()
+        .combinations(5 - held.len())
+        .map(|drawn| {
+            let cards: [u8; 5] = held.iter().chain(drawn).copied().collect::<Vec<u8>>().try_into().unwrap();
+            Hand::classify(&cards, ruleset)
+        })
+        .counts();
+    let total = outcomes.values().sum::<usize>() as f64;
+
+    Ok(outcomes.into_iter()
+        .sorted()
+        .map(|(hand_type, count)| (hand_type, count as f64 / total))
+        .collect())
+}
+
+// Four of each card, less any already in the hand
+fn default_deck(partial: &str, ruleset: &Ruleset) -> String {
+    ruleset.card_order.iter().flat_map(|&card| {
+        let held = partial.chars().filter(|&c| c == card).count();
+        std::iter::repeat_n(card, 4usize.saturating_sub(held))
+    }).collect()
+}
+
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
     let data = input_for(7).unwrap();
 
-    let hands = parse_data(&data, 1);
+    if let Some(ruleset_name) = args.iter().position(|arg| arg == "--ruleset").and_then(|ix| args.get(ix + 1)) {
+        let ruleset = match ruleset_name.as_str() {
+            "standard" => Ruleset::standard(),
+            "jokers" => Ruleset::jokers_wild(),
+            "deuces-and-jokers" => Ruleset::jokers_wild().with_wild('2'),
+            "straights" => Ruleset::poker_straights(),
+            unknown => panic!("Unknown ruleset {}", unknown)
+        };
+        let hands = parse_data(&data, &ruleset);
+        for (hand_type, count) in hands.iter().map(|hand| hand.classification).counts().into_iter().sorted() {
+            println!("{:?}: {}", hand_type, count);
+        }
+        println!("Score: {}", score(&hands));
+        return;
+    }
+
+    if let Some(ix) = args.iter().position(|arg| arg == "--equity") {
+        let partial = &args[ix + 1];
+        for ruleset in [Ruleset::standard(), Ruleset::jokers_wild()] {
+            let deck = args.get(ix + 2).cloned().unwrap_or_else(|| default_deck(partial, &ruleset));
+            let chances = match equity(partial, &deck, &ruleset) {
+                Ok(chances) => chances,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            println!("Wild cards {:?}:", ruleset.wild_cards);
+            for (hand_type, chance) in chances {
+                println!("  {:?}: {:.2}%", hand_type, chance * 100.0);
+            }
+        }
+        return;
+    }
+
+    if args.iter().any(|arg| arg == "--table") {
+        let standard = StrengthTable::build(&Ruleset::standard());
+        let jokers = StrengthTable::build(&Ruleset::jokers_wild());
+        let jokers_counts = jokers.type_counts();
+        for (hand_type, standard_count) in standard.type_counts() {
+            let jokers_count = jokers_counts.iter().find(|(t, _)| *t == hand_type).map_or(0, |(_, count)| *count);
+            println!("{:?}: {} -> {}", hand_type, standard_count, jokers_count);
+        }
+
+        // Where each of our hands sits among every possible hand, before and after jokers become wild
+        let card_strs = data.lines().map(|line| line.split_once(' ').unwrap().0);
+        for card_str in card_strs {
+            let before = standard.rank_of(&Hand::new(card_str, 0, &Ruleset::standard()));
+            let after = jokers.rank_of(&Hand::new(card_str, 0, &Ruleset::jokers_wild()));
+            println!("{}: {} -> {} ({:+})", card_str, before, after, after as i64 - before as i64);
+        }
+        return;
+    }
+
+    let hands = parse_data(&data, &Ruleset::standard());
     let answer_one = score(&hands);
     println!("Part one: {}", answer_one);
 
-    let hands = parse_data(&data, 2);
+    let hands = parse_data(&data, &Ruleset::jokers_wild());
     let answer_two = score(&hands);
     println!("Part two: {}", answer_two);
 }
 
-fn parse_data(data: &str, part: u8) -> Vec<Hand> {
+fn parse_data(data: &str, ruleset: &Ruleset) -> Vec<Hand> {
     data.lines().map(|line| {
         let (card_str, bid_str) = line.split_once(' ').unwrap();
         let bid = bid_str.parse::<u32>().unwrap();
-        Hand::new(card_str, bid, part)
+        Hand::new(card_str, bid, ruleset)
     }).collect()
 }
 
 fn score(hands: &[Hand]) -> u32 {
-    hands.iter().sorted_by_key(|&hand| (&hand.classification, hand.cards)).enumerate().map(|(rank, hand)| {
+    hands.iter().sorted_by_key(|&hand| hand.strength).enumerate().map(|(rank, hand)| {
         (rank as u32 + 1) * hand.bid
     }).sum()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483";
+
+    fn classify(card_str: &str, ruleset: &Ruleset) -> HandType {
+        Hand::new(card_str, 0, ruleset).classification
+    }
+
+    #[test]
+    fn test_example_scores() {
+        assert_eq!(score(&parse_data(EXAMPLE, &Ruleset::standard())), 6440);
+        assert_eq!(score(&parse_data(EXAMPLE, &Ruleset::jokers_wild())), 5905);
+    }
+
+    #[test]
+    fn test_extra_wild_card() {
+        let ruleset = Ruleset::jokers_wild().with_wild('2');
+        assert_eq!(classify("2J345", &ruleset), ThreeKind);
+        assert_eq!(classify("22JJA", &ruleset), FiveKind);
+        assert_eq!(classify("2KKQQ", &ruleset), FullHouse);
+        // A deuce is still a natural card under the plain joker rules
+        assert_eq!(classify("2J345", &Ruleset::jokers_wild()), OnePair);
+    }
+
+    #[test]
+    fn test_poker_straights() {
+        let ruleset = Ruleset::poker_straights();
+        assert_eq!(classify("23456", &ruleset), Straight);
+        assert_eq!(classify("TJQKA", &ruleset), Straight);
+        assert_eq!(classify("A2345", &ruleset), Straight);
+        assert_eq!(classify("5A342", &ruleset), Straight);
+        // Straights don't wrap round past the ace
+        assert_eq!(classify("QKA23", &ruleset), HighCard);
+        assert_eq!(classify("23457", &ruleset), HighCard);
+        assert_eq!(classify("23456", &Ruleset::standard()), HighCard);
+
+        let strength = |card_str| Hand::new(card_str, 0, &ruleset).strength;
+        assert!(strength("A2345") < strength("23456"));
+        assert!(strength("23456") < strength("TJQKA"));
+        assert!(strength("22233") < strength("AAAKK"));
+        assert!(strength("TJQKA") < strength("22233"));
+        assert!(strength("AAAKQ") < strength("A2345"));
+    }
+
+    #[test]
+    fn test_strength_table() {
+        let table = StrengthTable::build(&Ruleset::standard());
+        assert_eq!(table.hands.len(), 13usize.pow(5));
+        assert_eq!(table.type_counts(), vec![
+            (HighCard, 154440),
+            (OnePair, 171600),
+            (TwoPair, 25740),
+            (ThreeKind, 17160),
+            (FullHouse, 1560),
+            (FourKind, 780),
+            (FiveKind, 13)
+        ]);
+
+        let ruleset = Ruleset::standard();
+        let rank_of = |card_str| table.rank_of(&Hand::new(card_str, 0, &ruleset));
+        assert_eq!(rank_of("23456"), 0);
+        // Every high card is weaker than the weakest pair
+        assert_eq!(rank_of("22345"), 154440);
+        assert_eq!(rank_of("AAAAA"), 13usize.pow(5) - 1);
+    }
+
+    #[test]
+    fn test_equity() {
+        let ruleset = Ruleset::standard();
+        assert_eq!(equity("AAAA", &default_deck("AAAA", &ruleset), &ruleset), Ok(vec![(FourKind, 1.0)]));
+
+        let chances = equity("AAAAK", "", &ruleset).unwrap();
+        assert_eq!(chances, vec![(FourKind, 1.0)]);
+        let chances = equity("AAKK", "AKQ", &ruleset).unwrap();
+        assert_eq!(chances.len(), 2);
+        assert!((chances[0].1 - 1.0 / 3.0).abs() < 1e-12 && chances[0].0 == TwoPair);
+        assert!((chances[1].1 - 2.0 / 3.0).abs() < 1e-12 && chances[1].0 == FullHouse);
+
+        assert!(equity("AAAAKK", "", &ruleset).is_err());
+        assert!(equity("AAAX", "23456", &ruleset).is_err());
+        assert!(equity("AAA", "2X", &ruleset).is_err());
+        assert!(equity("AAA", "2", &ruleset).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::DesertMap;
+
+fn neighbours<'a>(id: &str, locations: &DesertMap<'a>) -> [&'a str; 2] {
+    let location = locations.get(id).unwrap();
+    [location.left, location.right]
+}
+
+pub fn to_dot(locations: &DesertMap) -> String {
+    let mut dot = "digraph desert {\n".to_string();
+    for id in locations.keys().sorted() {
+        let style = if id.ends_with('A') {
+            " [style=filled, fillcolor=palegreen]"
+        } else if id.ends_with('Z') {
+            " [style=filled, fillcolor=salmon]"
+        } else {
+            ""
+        };
+        dot.push_str(&format!("  \"{}\"{};\n", id, style));
+
+        let [left, right] = neighbours(id, locations);
+        dot.push_str(&format!("  \"{}\" -> \"{}\" [label=L];\n", id, left));
+        dot.push_str(&format!("  \"{}\" -> \"{}\" [label=R];\n", id, right));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+pub fn reachable_from<'a>(start: &'a str, locations: &DesertMap<'a>) -> HashSet<&'a str> {
+    let mut seen = HashSet::from([start]);
+    let mut to_visit = vec![start];
+    while let Some(id) = to_visit.pop() {
+        for next in neighbours(id, locations) {
+            if seen.insert(next) {
+                to_visit.push(next);
+            }
+        }
+    }
+    seen
+}
+
+// Kosaraju's algorithm: order the nodes by when a DFS finishes with them, then peel off
+// components by searching the reversed graph in the opposite order.
+pub fn strongly_connected_components<'a>(locations: &DesertMap<'a>) -> Vec<Vec<&'a str>> {
+    let mut finished = Vec::<&str>::new();
+    let mut seen = HashSet::<&str>::new();
+    for &root in locations.keys().sorted() {
+        if !seen.insert(root) {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        while let Some((id, next_ix)) = stack.pop() {
+            if next_ix < 2 {
+                stack.push((id, next_ix + 1));
+                let next = neighbours(id, locations)[next_ix];
+                if seen.insert(next) {
+                    stack.push((next, 0));
+                }
+            } else {
+                finished.push(id);
+            }
+        }
+    }
+
+    let mut reversed = HashMap::<&str, Vec<&str>>::new();
+    for (&id, location) in locations {
+        reversed.entry(location.left).or_default().push(id);
+        reversed.entry(location.right).or_default().push(id);
+    }
+
+    let mut assigned = HashSet::<&str>::new();
+    let mut components = vec![];
+    for &root in finished.iter().rev() {
+        if !assigned.insert(root) {
+            continue;
+        }
+        let mut component = vec![root];
+        let mut to_visit = vec![root];
+        while let Some(id) = to_visit.pop() {
+            for &previous in reversed.get(id).into_iter().flatten() {
+                if assigned.insert(previous) {
+                    component.push(previous);
+                    to_visit.push(previous);
+                }
+            }
+        }
+        component.sort();
+        components.push(component);
+    }
+    components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+    components
+}
+
+// For each ..A node, the ..Z nodes it can ever get to (following either turn at every step)
+pub fn start_end_reachability<'a>(locations: &DesertMap<'a>) -> Vec<(&'a str, Vec<&'a str>)> {
+    locations.keys()
+        .filter(|id| id.ends_with('A'))
+        .sorted()
+        .map(|&start| {
+            let ends = reachable_from(start, locations)
+                .into_iter()
+                .filter(|id| id.ends_with('Z'))
+                .sorted()
+                .collect();
+            (start, ends)
+        })
+        .collect()
+}
+
+// Nodes that no ..A node can get to
+pub fn unreachable_nodes<'a>(locations: &DesertMap<'a>) -> Vec<&'a str> {
+    let reachable = locations.keys()
+        .filter(|id| id.ends_with('A'))
+        .flat_map(|&start| reachable_from(start, locations))
+        .collect::<HashSet<&str>>();
+    locations.keys().filter(|id| !reachable.contains(*id)).copied().sorted().collect()
+}
+
+// Nodes from which no ..Z node can ever be reached
+pub fn dead_ends<'a>(locations: &DesertMap<'a>) -> Vec<&'a str> {
+    locations.keys()
+        .filter(|&&id| !reachable_from(id, locations).iter().any(|next| next.ends_with('Z')))
+        .copied()
+        .sorted()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_data;
+
+    // The two-ghost example, plus a node nothing starts from
+    const EXAMPLE: &str = "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+33B = (XXX, 11Z)
+XXX = (XXX, XXX)";
+
+    #[test]
+    fn test_components() {
+        let (_, locations) = parse_data(EXAMPLE);
+        let components = strongly_connected_components(&locations);
+        assert_eq!(components[0], vec!["22B", "22C", "22Z"]);
+        assert_eq!(components[1], vec!["11B", "11Z"]);
+        let singletons = components[2..].iter().map(|component| component.as_slice()).sorted().collect::<Vec<_>>();
+        assert_eq!(singletons, vec![["11A"], ["22A"], ["33B"], ["XXX"]]);
+    }
+
+    #[test]
+    fn test_reachability() {
+        let (_, locations) = parse_data(EXAMPLE);
+        assert_eq!(start_end_reachability(&locations), vec![("11A", vec!["11Z"]), ("22A", vec!["22Z"])]);
+        assert_eq!(unreachable_nodes(&locations), vec!["33B"]);
+        assert_eq!(dead_ends(&locations), vec!["XXX"]);
+    }
+
+    #[test]
+    fn test_dot() {
+        let (_, locations) = parse_data(EXAMPLE);
+        let dot = to_dot(&locations);
+        assert!(dot.starts_with("digraph desert {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  \"11A\" [style=filled, fillcolor=palegreen];\n"));
+        assert!(dot.contains("  \"22Z\" [style=filled, fillcolor=salmon];\n"));
+        assert!(dot.contains("  \"XXX\";\n"));
+        assert!(dot.contains("  \"11B\" -> \"XXX\" [label=L];\n  \"11B\" -> \"11Z\" [label=R];\n"));
+        assert_eq!(dot.matches(" -> ").count(), 2 * locations.len());
+    }
+}
@@ -1,9 +1,12 @@
-use std::collections::HashMap;
-use num::integer::lcm;
+use std::{collections::HashMap, env};
+use itertools::Itertools;
+use num::integer::{ExtendedGcd, Integer};
 
 use input_curler::input_for;
 use regex::Regex;
 
+mod analysis;
+
 struct Location<'a> {
     left: &'a str,
     right: &'a str
@@ -24,13 +27,32 @@ fn main() {
 // XXX = (XXX, XXX)".to_string();
     let (directions, locations) = parse_data(&data);
 
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--dot") {
+        print!("{}", analysis::to_dot(&locations));
+        return;
+    }
+    if args.iter().any(|arg| arg == "--analyse") {
+        let components = analysis::strongly_connected_components(&locations);
+        println!("{} strongly connected components; largest sizes {:?}", components.len(),
+            components.iter().take(10).map(Vec::len).collect::<Vec<usize>>());
+        for (start, ends) in analysis::start_end_reachability(&locations) {
+            println!("{} can reach {:?}", start, ends);
+        }
+        println!("Unreachable from any start: {:?}", analysis::unreachable_nodes(&locations));
+        println!("Can't reach any end: {:?}", analysis::dead_ends(&locations));
+        return;
+    }
+
     let answer_one = part_one(directions, &locations);
     println!("Part one: {}", answer_one);
-    let answer_two = part_two(directions, &locations);
-    println!("Part two: {}", answer_two);
+    match part_two(directions, &locations) {
+        Some(answer_two) => println!("Part two: {}", answer_two),
+        None => println!("Part two: the ghosts never all align")
+    }
 }
 
-fn parse_data (data: &str) -> (&str, DesertMap) {
+fn parse_data (data: &str) -> (&str, DesertMap<'_>) {
     let mut lines = data.lines();
     let directions = lines.next().unwrap();
 
@@ -67,29 +89,160 @@ fn part_one(directions: &str, locations: &DesertMap) -> u32 {
     steps
 }
 
-fn part_two(directions: &str, locations: &DesertMap) -> u64 {
-    let current_ids = locations
-        .keys()
-        .filter_map(|k| if k.ends_with('A') {
-            Some(*k)
-        } else {
-            None
-        }).collect::<Vec<&str>>();
-
-    current_ids.iter().map(|start| {
-        let mut current_id = *start;
-        let mut cyclic_directions = directions.chars().cycle();
+// A ghost's walk is determined by its (node, instruction index) state, so it must eventually
+// loop. Steps are counted from the start; a hit is a step on which the ghost stands on a ..Z node.
+#[derive(Debug)]
+struct GhostCycle {
+    prefix_len: u64,
+    cycle_len: u64,
+    // Hits before the loop starts, which only happen once
+    prefix_hits: Vec<u64>,
+    // Hits within the first time round the loop; they recur every cycle_len steps
+    cycle_hits: Vec<u64>
+}
+impl GhostCycle {
+    fn trace(start: &str, directions: &str, locations: &DesertMap) -> Self {
+        let directions = directions.chars().collect::<Vec<char>>();
+        let mut seen = HashMap::<(&str, usize), u64>::new();
+        let mut hits = vec![];
+        let mut current_id = start;
         let mut steps = 0u64;
-        while !current_id.ends_with('Z') {
-            steps += 1;
-            let current = locations.get(current_id).unwrap();
-            if cyclic_directions.next().unwrap() == 'L' {
-                current_id = current.left;
-            } else {
-                current_id = current.right;
+
+        loop {
+            let instruction_ix = (steps % directions.len() as u64) as usize;
+            if let Some(&first_seen) = seen.get(&(current_id, instruction_ix)) {
+                let (prefix_hits, cycle_hits) = hits.iter().partition(|&&hit| hit < first_seen);
+                return Self {
+                    prefix_len: first_seen,
+                    cycle_len: steps - first_seen,
+                    prefix_hits,
+                    cycle_hits
+                };
+            }
+            seen.insert((current_id, instruction_ix), steps);
+            if current_id.ends_with('Z') {
+                hits.push(steps);
             }
+
+            let current = locations.get(current_id).unwrap();
+            current_id = if directions[instruction_ix] == 'L' { current.left } else { current.right };
+            steps += 1;
         }
-        steps
-    }).reduce(lcm).unwrap()
+    }
+
+    fn hits_at(&self, steps: u64) -> bool {
+        if steps < self.prefix_len {
+            self.prefix_hits.contains(&steps)
+        } else {
+            let in_first_cycle = self.prefix_len + (steps - self.prefix_len) % self.cycle_len;
+            self.cycle_hits.contains(&in_first_cycle)
+        }
+    }
+}
 
+// Merges t = a1 (mod m1) and t = a2 (mod m2) into a single congruence, if they're compatible.
+// The moduli needn't be coprime.
+fn combine_congruences((a1, m1): (i128, i128), (a2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let ExtendedGcd { gcd, x, .. } = m1.extended_gcd(&m2);
+    if (a2 - a1) % gcd != 0 {
+        return None;
+    }
+    let modulus = m1 / gcd * m2;
+    let k = ((a2 - a1) / gcd * x).rem_euclid(m2 / gcd);
+    Some(((a1 + m1 * k).rem_euclid(modulus), modulus))
+}
+
+// The first step on which every ghost is on a ..Z node, or None if that never happens
+fn all_ghosts_align(ghosts: &[GhostCycle]) -> Option<u64> {
+    // A one-off hit from some ghost's prefix might line up with everyone else
+    let from_prefixes = ghosts.iter()
+        .flat_map(|ghost| ghost.prefix_hits.iter().copied())
+        .filter(|&steps| ghosts.iter().all(|ghost| ghost.hits_at(steps)))
+        .min();
+    if from_prefixes.is_some() {
+        return from_prefixes;
+    }
+
+    // Otherwise every ghost must be in its loop, so pick one recurring hit for each and solve with the CRT
+    ghosts.iter()
+        .map(|ghost| ghost.cycle_hits.iter().map(move |&hit| (hit, ghost.cycle_len)))
+        .multi_cartesian_product()
+        .filter_map(|choice| {
+            let earliest = choice.iter().map(|&(hit, _)| hit).max().unwrap();
+            let (residue, modulus) = choice.iter()
+                .map(|&(hit, cycle_len)| (hit as i128, cycle_len as i128))
+                .try_fold((0, 1), combine_congruences)?;
+            // Lift the solution until every ghost has reached its chosen hit
+            let earliest = earliest as i128;
+            let cycles_short = ((earliest - residue).max(0) + modulus - 1) / modulus;
+            Some((residue + cycles_short * modulus) as u64)
+        })
+        .min()
+}
+
+fn part_two(directions: &str, locations: &DesertMap) -> Option<u64> {
+    let ghosts = locations
+        .keys()
+        .filter(|k| k.ends_with('A'))
+        .map(|start| GhostCycle::trace(start, directions, locations))
+        .collect::<Vec<GhostCycle>>();
+
+    all_ghosts_align(&ghosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ghosts_align(data: &str) -> Option<u64> {
+        let (directions, locations) = parse_data(data);
+        part_two(directions, &locations)
+    }
+
+    #[test]
+    fn test_example() {
+        let data = "LR
+
+11A = (11B, XXX)
+11B = (XXX, 11Z)
+11Z = (11B, XXX)
+22A = (22B, XXX)
+22B = (22C, 22C)
+22C = (22Z, 22Z)
+22Z = (22B, 22B)
+XXX = (XXX, XXX)";
+        assert_eq!(ghosts_align(data), Some(6));
+    }
+
+    #[test]
+    fn test_never_aligns() {
+        // The first ghost is on 11Z on odd steps only, the second on 22Z on even steps only
+        let data = "L
+
+11A = (11Z, 11Z)
+11Z = (11A, 11A)
+22A = (22B, 22B)
+22B = (22Z, 22Z)
+22Z = (22B, 22B)";
+        assert_eq!(ghosts_align(data), None);
+    }
+
+    #[test]
+    fn test_aligns_in_prefix() {
+        // The first ghost passes 33Z once, on step 2, then loops forever on 33C
+        let data = "L
+
+33A = (33B, 33B)
+33B = (33Z, 33Z)
+33Z = (33C, 33C)
+33C = (33C, 33C)
+44A = (44B, 44B)
+44B = (44Z, 44Z)
+44Z = (44A, 44A)";
+        assert_eq!(ghosts_align(data), Some(2));
+
+        let (directions, locations) = parse_data(data);
+        let ghost = GhostCycle::trace("33A", directions, &locations);
+        assert_eq!((ghost.prefix_len, ghost.cycle_len, ghost.prefix_hits, ghost.cycle_hits), (3, 1, vec![2], vec![]));
+    }
 }
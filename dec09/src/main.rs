@@ -1,64 +1,247 @@
+use std::{env, fmt::Display, io::{self, BufRead}, str::FromStr};
+
 use input_curler::input_for;
+use num::{BigInt, CheckedAdd, CheckedSub};
+
+#[derive(Debug, PartialEq, Eq)]
+struct Overflow;
+
+// The edges of a difference table, updated a reading at a time. `leading` holds the first entry
+// of each row, which never changes once written; `trailing` holds the last entry of each row.
+#[derive(Debug)]
+struct DifferenceTable<T> {
+    leading: Vec<T>,
+    trailing: Vec<T>
+}
+impl<T: Clone + CheckedAdd + CheckedSub> DifferenceTable<T> {
+    fn from_readings(readings: impl IntoIterator<Item = T>) -> Result<Self, Overflow> {
+        let mut table = Self { leading: vec![], trailing: vec![] };
+        for reading in readings {
+            table.push(reading)?;
+        }
+        Ok(table)
+    }
+
+    // Adds a row's worth of new trailing edges without touching the rest of the table. Leaves the
+    // table unchanged on overflow.
+    fn push(&mut self, reading: T) -> Result<(), Overflow> {
+        let mut trailing = Vec::with_capacity(self.trailing.len() + 1);
+        let mut carry = reading;
+        for previous in &self.trailing {
+            let difference = carry.checked_sub(previous).ok_or(Overflow)?;
+            trailing.push(carry);
+            carry = difference;
+        }
+        trailing.push(carry.clone());
+
+        self.leading.push(carry);
+        self.trailing = trailing;
+        Ok(())
+    }
+
+    // None until there is at least one reading
+    fn next_value(&self) -> Result<Option<T>, Overflow> {
+        let mut edges = self.trailing.iter().rev();
+        let Some(deepest) = edges.next() else { return Ok(None) };
+        edges.try_fold(deepest.clone(), |below, edge| edge.checked_add(&below).ok_or(Overflow)).map(Some)
+    }
+
+    fn prev_value(&self) -> Result<Option<T>, Overflow> {
+        let mut edges = self.leading.iter().rev();
+        let Some(deepest) = edges.next() else { return Ok(None) };
+        edges.try_fold(deepest.clone(), |below, edge| edge.checked_sub(&below).ok_or(Overflow)).map(Some)
+    }
+}
+
+// The lowest-degree polynomial through a run of readings, held as Newton forward differences:
+// value_at(n) = sum over k of leading[k] * C(n, k), with index 0 being the first reading.
+#[derive(Debug)]
+struct Sequence {
+    leading: Vec<i128>,
+    // Whether the readings run on past the polynomial's degree, so a row of zeros confirms it
+    confirmed: bool
+}
+impl Sequence {
+    fn fit(readings: &[i128]) -> Result<Self, Overflow> {
+        let mut leading = DifferenceTable::from_readings(readings.iter().copied())?.leading;
+        // Once the differences after the degree are all zero, so is everything below them
+        let fitted_len = leading.iter().rposition(|&n| n != 0).map_or(0, |ix| ix + 1);
+        leading.truncate(fitted_len);
+
+        Ok(Self { leading, confirmed: fitted_len < readings.len() })
+    }
+
+    // None for a sequence of zeros
+    fn degree(&self) -> Option<usize> {
+        self.leading.len().checked_sub(1)
+    }
+
+    fn is_polynomial(&self) -> bool {
+        self.confirmed
+    }
+
+    // Works for any whole index, including before the first reading. None if the result won't fit.
+    fn value_at(&self, index: i128) -> Option<i128> {
+        let mut total = 0i128;
+        let mut binomial = 1i128;
+        for (k, &difference) in self.leading.iter().enumerate() {
+            if k > 0 {
+                // C(n, k) = C(n, k - 1) * (n - k + 1) / k, and the division is always exact
+                binomial = binomial.checked_mul(index.checked_sub(k as i128 - 1)?)? / k as i128;
+            }
+            total = total.checked_add(difference.checked_mul(binomial)?)?;
+        }
+        Some(total)
+    }
+}
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Some(ix) = args.iter().position(|arg| arg == "--stream") {
+        match args.get(ix + 1).map(String::as_str) {
+            Some("big") => stream::<BigInt>(),
+            Some("i128") => stream::<i128>(),
+            _ => stream::<i64>()
+        }
+        return;
+    }
+
     let data = input_for(9).unwrap();
 
-    let sequences = data.lines().map(|line| line.split_whitespace().map(|n| n.parse::<i32>().unwrap()));
+    let sequences = parse_data(&data);
+
+    for (ix, (_, sequence)) in sequences.iter().enumerate().filter(|(_, (_, sequence))| !sequence.is_polynomial()) {
+        println!("Warning: sequence {} is too short to confirm degree {:?}", ix + 1, sequence.degree());
+    }
 
-    let answer_one = part_one(sequences.clone());
+    let answer_one = part_one(&sequences);
     println!("Part one: {}", answer_one);
-    let answer_two = part_two(sequences.clone());
+    let answer_two = part_two(&sequences);
     println!("Part two: {}", answer_two);
+
+    if let Some(index) = args.iter().position(|arg| arg == "--at").and_then(|ix| args.get(ix + 1)) {
+        let index = index.parse::<i128>().unwrap();
+        for (_, sequence) in &sequences {
+            match sequence.value_at(index) {
+                Some(value) => println!("{}", value),
+                None => println!("overflow")
+            }
+        }
+    }
+}
+
+fn parse_data(data: &str) -> Vec<(i128, Sequence)> {
+    data.lines().map(|line| {
+        let readings = line.split_whitespace().map(|n| n.parse::<i128>().unwrap()).collect::<Vec<i128>>();
+        (readings.len() as i128, Sequence::fit(&readings).unwrap())
+    }).collect()
 }
 
-fn part_one<Iter, InnerIter>(sequences: Iter) -> i32
-where Iter: Iterator<Item = InnerIter>,
-    InnerIter: Iterator<Item =i32> + Clone
-{
-    sequences.map(|seq| next_value(seq)).sum()
+fn part_one(sequences: &[(i128, Sequence)]) -> i128 {
+    sequences.iter().map(|(len, sequence)| sequence.value_at(*len).unwrap()).sum()
 }
 
-fn next_value<Iter>(sequence: Iter) -> i32
-where Iter: Iterator<Item = i32> + Clone
-{
-    let mut lasts = vec![sequence.clone().last().unwrap()];
+fn part_two(sequences: &[(i128, Sequence)]) -> i128 {
+    sequences.iter().map(|(_, sequence)| sequence.value_at(-1).unwrap()).sum()
+}
 
-    let mut working_seq = sequence.collect::<Vec<i32>>();
-    loop {
-        let diffs = working_seq.windows(2).map(|pair| pair[1] - pair[0]);
-        if diffs.clone().all(|n| n == 0) {
-            break;
+// Reads one reading per line from stdin, reporting the extrapolations after each
+fn stream<T>() where T: Clone + CheckedAdd + CheckedSub + FromStr + Display, T::Err: std::fmt::Debug {
+    let mut table = DifferenceTable::<T> { leading: vec![], trailing: vec![] };
+    for line in io::stdin().lock().lines() {
+        let reading = line.unwrap().trim().parse::<T>().unwrap();
+        if table.push(reading).is_err() {
+            println!("overflow: reading ignored");
+            continue;
+        }
+        match (table.prev_value(), table.next_value()) {
+            (Ok(Some(prev)), Ok(Some(next))) => println!("prev {}, next {}", prev, next),
+            _ => println!("overflow extrapolating")
         }
-        working_seq = diffs.collect();
-        lasts.push(*working_seq.last().unwrap());
     }
-
-    lasts.iter().sum()
 }
 
-fn part_two<Iter, InnerIter>(sequences: Iter) -> i32
-where Iter: Iterator<Item = InnerIter>,
-    InnerIter: Iterator<Item =i32> + Clone
-{
-    sequences.map(|seq| prev_value(seq)).sum()
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn prev_value<Iter>(sequence: Iter) -> i32
-where Iter: Iterator<Item = i32> + Clone
-{
-    let mut firsts = vec![sequence.clone().next().unwrap()];
+    const EXAMPLE: &str = "0 3 6 9 12 15
+1 3 6 10 15 21
+10 13 16 21 30 45";
 
-    let mut working_seq = sequence.collect::<Vec<i32>>();
-    loop {
-        let diffs = working_seq.windows(2).map(|pair| pair[1] - pair[0]);
-        if diffs.clone().all(|n| n == 0) {
-            break;
-        }
-        working_seq = diffs.collect();
-        firsts.push(*working_seq.first().unwrap());
+    #[test]
+    fn test_example() {
+        let sequences = parse_data(EXAMPLE);
+        assert_eq!(part_one(&sequences), 114);
+        assert_eq!(part_two(&sequences), 2);
+        let degrees = sequences.iter().map(|(_, sequence)| sequence.degree()).collect::<Vec<Option<usize>>>();
+        assert_eq!(degrees, vec![Some(1), Some(2), Some(3)]);
+        assert!(sequences.iter().all(|(_, sequence)| sequence.is_polynomial()));
+    }
+
+    #[test]
+    fn test_unconfirmed_degree() {
+        let doubling = Sequence::fit(&[1, 2, 4, 8, 16]).unwrap();
+        assert_eq!(doubling.degree(), Some(4));
+        assert!(!doubling.is_polynomial());
+
+        let zeros = Sequence::fit(&[0, 0, 0]).unwrap();
+        assert_eq!(zeros.degree(), None);
+        assert!(zeros.is_polynomial());
+        assert_eq!(zeros.value_at(1_000), Some(0));
+    }
+
+    #[test]
+    fn test_value_at() {
+        // The triangular numbers, shifted so index 0 is 1
+        let triangular = Sequence::fit(&[1, 3, 6, 10, 15, 21]).unwrap();
+        assert_eq!(triangular.value_at(1_000), Some(1001 * 1002 / 2));
+        assert_eq!(triangular.value_at(-1), Some(0));
+        assert_eq!(triangular.value_at(-2), Some(0));
+        assert_eq!(triangular.value_at(-3), Some(1));
+        assert_eq!(triangular.value_at(-1_000), Some(-999 * -998 / 2));
+
+        let linear = Sequence::fit(&[0, 3, 6, 9]).unwrap();
+        assert_eq!(linear.value_at(10i128.pow(30)), Some(3 * 10i128.pow(30)));
+        assert_eq!(linear.value_at(-7), Some(-21));
+        assert_eq!(triangular.value_at(i128::MAX), None);
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let table = DifferenceTable::<i64>::from_readings([]).unwrap();
+        assert_eq!(table.next_value(), Ok(None));
+        assert_eq!(table.prev_value(), Ok(None));
     }
 
-    firsts.iter().enumerate().map(|(ix, n)|
-        if ix % 2 == 0 { *n } else { -n }
-    ).sum()
-}
\ No newline at end of file
+    #[test]
+    fn test_push_overflow_leaves_table_unchanged() {
+        let mut table = DifferenceTable::<i8>::from_readings([100]).unwrap();
+        // -100 - 100 doesn't fit in an i8
+        assert_eq!(table.push(-100), Err(Overflow));
+        assert_eq!((table.leading.clone(), table.trailing.clone()), (vec![100], vec![100]));
+        assert_eq!(table.next_value(), Ok(Some(100)));
+
+        table.push(90).unwrap();
+        assert_eq!(table.next_value(), Ok(Some(80)));
+        assert_eq!(table.prev_value(), Ok(Some(110)));
+        assert_eq!(table.push(-128), Err(Overflow));
+        assert_eq!((table.leading, table.trailing), (vec![100, -10], vec![90, -10]));
+    }
+
+    #[test]
+    fn test_streaming_matches_batch() {
+        let readings = [10i64, 13, 16, 21, 30, 45, -4, 7];
+        let mut streamed = DifferenceTable::from_readings([]).unwrap();
+        for (ix, &reading) in readings.iter().enumerate() {
+            streamed.push(reading).unwrap();
+            let batch = DifferenceTable::from_readings(readings[..=ix].iter().copied()).unwrap();
+            assert_eq!(streamed.next_value(), batch.next_value());
+            assert_eq!(streamed.prev_value(), batch.prev_value());
+
+            let sequence = Sequence::fit(&readings[..=ix].iter().map(|&n| n as i128).collect::<Vec<i128>>()).unwrap();
+            assert_eq!(streamed.next_value().unwrap().map(i128::from), sequence.value_at(ix as i128 + 1));
+            assert_eq!(streamed.prev_value().unwrap().map(i128::from), sequence.value_at(-1));
+        }
+    }
+}
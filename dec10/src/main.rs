@@ -1,8 +1,9 @@
+use std::{collections::HashSet, env, fmt, fs};
+
 use input_curler::input_for;
 
 #[derive(Debug, Clone, Copy)]
 struct Pipe {
-    location: (usize, usize),
     shape: char,
     exits: Option<[(usize, usize); 2]>,
     dist_from_s: Option<usize>,
@@ -12,7 +13,6 @@ impl Pipe {
     fn new(location: (usize, usize), shape: char, max_x: usize, max_y: usize) -> Self {
         if shape == '.' {
             return Self {
-                location,
                 shape,
                 exits: None,
                 dist_from_s: None,
@@ -34,7 +34,6 @@ impl Pipe {
             _ => unreachable!()
         };
         Self {
-            location,
             shape,
             exits: maybe_exits,
             dist_from_s: None,
@@ -69,6 +68,94 @@ impl PipeMap {
     fn width(&self) -> usize {
         self.pipes[0].len()
     }
+
+    // Records whether each tile off the loop is inside it, for rendering
+    fn mark_enclosed(&mut self, pipe_loop: &PipeLoop) {
+        let enclosed = pipe_loop.enclosed_cells(self);
+        for (row, pipe_row) in self.pipes.iter_mut().enumerate() {
+            for (col, pipe) in pipe_row.iter_mut().enumerate() {
+                if pipe.dist_from_s.is_none() {
+                    pipe.inside = Some(enclosed.contains(&(row, col)));
+                }
+            }
+        }
+    }
+
+    // Ten pixels a tile, with the loop drawn heavily and any other pipes drawn faintly over a
+    // background showing which tiles are inside
+    fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+            self.width() * 10,
+            self.height() * 10
+        );
+        for (row, pipe_row) in self.pipes.iter().enumerate() {
+            for (col, pipe) in pipe_row.iter().enumerate() {
+                let fill = match pipe.inside {
+                    Some(true) => "palegreen",
+                    Some(false) => "lightblue",
+                    None => continue
+                };
+                svg.push_str(&format!("  <rect x=\"{}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/>\n", col * 10, row * 10, fill));
+            }
+        }
+        for (row, pipe_row) in self.pipes.iter().enumerate() {
+            for (col, pipe) in pipe_row.iter().enumerate() {
+                let Some(exits) = pipe.exits else { continue };
+                let style = if pipe.dist_from_s.is_some() {
+                    "stroke=\"black\" stroke-width=\"3\""
+                } else {
+                    "stroke=\"grey\" stroke-width=\"1\""
+                };
+                // Each exit is half a tile's worth of line, from the centre to the shared edge
+                for (exit_row, exit_col) in exits {
+                    svg.push_str(&format!(
+                        "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {} stroke-linecap=\"square\"/>\n",
+                        col * 10 + 5,
+                        row * 10 + 5,
+                        (col + exit_col) * 5 + 5,
+                        (row + exit_row) * 5 + 5,
+                        style
+                    ));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+// Draws the map with box-drawing characters: the loop in bold, other pipes dimmed, and tiles
+// coloured green inside the loop or blue outside it once they've been marked
+impl fmt::Display for PipeMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for pipe_row in &self.pipes {
+            for pipe in pipe_row {
+                let glyph = match pipe.shape {
+                    '|' => '│',
+                    '-' => '─',
+                    'L' => '└',
+                    'J' => '┘',
+                    '7' => '┐',
+                    'F' => '┌',
+                    _ => '·'
+                };
+                let weight = match (pipe.dist_from_s, pipe.shape) {
+                    (Some(_), _) => "1",
+                    (None, '.') => "0",
+                    (None, _) => "2"
+                };
+                let colour = match pipe.inside {
+                    Some(true) => ";32",
+                    Some(false) => ";34",
+                    None => ""
+                };
+                write!(f, "\x1b[{}{}m{}\x1b[0m", weight, colour, glyph)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
 }
 
 fn main() {
@@ -85,10 +172,38 @@ fn main() {
 // L7JLJL-JLJLJL--JLJ.L".to_string();
 
     let (mut pipes, start) = parse_data(&data);
-    let answer_one = part_one(&mut pipes, start);
-    println!("Part one: {}", answer_one);
-    let answer_two = part_two(&mut pipes);
+    let (answer_one, farthest) = match part_one(&mut pipes, start) {
+        Ok(answer) => answer,
+        Err(error) => {
+            println!("Can't work out the start tile: {}", error);
+            return;
+        }
+    };
+    println!("Part one: {} (at {:?})", answer_one, farthest);
+    let answer_two = part_two(&pipes, start);
     println!("Part two: {}", answer_two);
+
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--loops") {
+        for pipe_loop in find_loops(&pipes) {
+            println!("Loop from {:?}: {} tiles, {} enclosed", pipe_loop.vertices[0], pipe_loop.len(), pipe_loop.enclosed_count());
+        }
+    }
+    if args.iter().any(|arg| arg == "--render") || args.iter().any(|arg| arg == "--svg") {
+        let mut rendered = pipes.clone();
+        rendered.mark_enclosed(&PipeLoop::trace(&pipes, start).unwrap());
+        if args.iter().any(|arg| arg == "--render") {
+            print!("{}", rendered);
+        }
+        if let Some(path) = args.iter().position(|arg| arg == "--svg").and_then(|ix| args.get(ix + 1)) {
+            fs::write(path, rendered.to_svg()).unwrap();
+        }
+    }
+    if args.iter().any(|arg| arg == "--cross-check") {
+        let pipe_loop = PipeLoop::trace(&pipes, start).unwrap();
+        println!("Scanned cells: {}", pipe_loop.enclosed_cells(&pipes).len());
+        println!("Ray cast: {}", ray_cast_count(&mut pipes));
+    }
 }
 
 fn parse_data(data: &str) -> (PipeMap, (usize, usize)) {
@@ -103,7 +218,6 @@ fn parse_data(data: &str) -> (PipeMap, (usize, usize)) {
             if cell == 'S' {
                 start = (y, x);
                 row.push(Pipe {
-                    location: start,
                     shape: 'S',
                     exits: None,
                     dist_from_s: Some(0),
@@ -119,74 +233,148 @@ fn parse_data(data: &str) -> (PipeMap, (usize, usize)) {
     (PipeMap { pipes }, start)
 }
 
-fn part_one(pipes: &mut PipeMap, start: (usize, usize)) -> usize {
-    let mut search_front = Vec::<(usize, usize)>::new();
-    if start.0 != 0 && pipes.at((start.0 - 1, start.1)).exits_to(start) {
-        search_front.push((start.0 - 1, start.1));
-    }
-    if start.0 != pipes.height() - 1 && pipes.at((start.0 + 1, start.1)).exits_to(start) {
-        search_front.push((start.0 + 1, start.1));
+#[derive(Debug)]
+enum StartError {
+    // No shape for S joins its neighbours up into a loop
+    NoLoop,
+    // More than one shape for S would close a loop
+    Ambiguous(Vec<char>)
+}
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::NoLoop => write!(f, "no shape for S closes a loop"),
+            StartError::Ambiguous(shapes) => write!(f, "S could be any of {:?}", shapes)
+        }
     }
-    if start.1 != 0 && pipes.at((start.0, start.1 - 1)).exits_to(start) {
-        search_front.push((start.0, start.1 - 1));
+}
+
+// Tries every shape S could be, keeping the ones that lead out and back round to S
+fn resolve_start(pipes: &PipeMap, start: (usize, usize)) -> Result<char, StartError> {
+    let (max_x, max_y) = (pipes.width() - 1, pipes.height() - 1);
+    let candidates = ['|', '-', 'L', 'J', '7', 'F'].into_iter().filter(|&shape|
+        Pipe::new(start, shape, max_x, max_y).exits
+            .and_then(|exits| PipeLoop::trace_from(pipes, start, exits))
+            .is_some()
+    ).collect::<Vec<char>>();
+
+    match candidates.as_slice() {
+        [] => Err(StartError::NoLoop),
+        [shape] => Ok(*shape),
+        _ => Err(StartError::Ambiguous(candidates))
     }
-    if start.1 != pipes.width() - 1 && pipes.at((start.0, start.1 + 1)).exits_to(start) {
-        search_front.push((start.0, start.1 + 1));
+}
+
+fn part_one(pipes: &mut PipeMap, start: (usize, usize)) -> Result<(usize, (usize, usize)), StartError> {
+    let shape = resolve_start(pipes, start)?;
+    let (max_x, max_y) = (pipes.width() - 1, pipes.height() - 1);
+    *pipes.at_mut(start) = Pipe { dist_from_s: Some(0), ..Pipe::new(start, shape, max_x, max_y) };
+
+    let pipe_loop = PipeLoop::trace(pipes, start).unwrap();
+    for (ix, &tile) in pipe_loop.vertices.iter().enumerate() {
+        pipes.at_mut(tile).dist_from_s = Some(ix.min(pipe_loop.len() - ix));
     }
 
-    if search_front.len() == 2 {
-        let start_pipe = pipes.at_mut(start);
-        if search_front.contains(&(start.0 - 1, start.1)) {
-            if search_front.contains(&(start.0, start.1 - 1)) {
-                start_pipe.shape = 'J';
-            } else if search_front.contains(&(start.0, start.1 + 1)) {
-                start_pipe.shape = 'L';
-            } else if search_front.contains(&(start.0 + 1, start.1)) {
-                start_pipe.shape = '|';
+    Ok(pipe_loop.farthest())
+}
+
+// Every closed loop in the map, each starting from its top-left tile. S must already be resolved.
+fn find_loops(pipes: &PipeMap) -> Vec<PipeLoop> {
+    let mut seen = HashSet::<(usize, usize)>::new();
+    let mut loops = vec![];
+    for row in 0..pipes.height() {
+        for col in 0..pipes.width() {
+            if seen.contains(&(row, col)) {
+                continue;
             }
-        } else if search_front.contains(&(start.0, start.1 - 1)) {
-            if search_front.contains(&(start.0, start.1 + 1)) {
-                start_pipe.shape = '-';
-            } else if search_front.contains(&(start.0 + 1, start.1)) {
-                start_pipe.shape = '7';
+            if let Some(pipe_loop) = PipeLoop::trace(pipes, (row, col)) {
+                seen.extend(pipe_loop.vertices.iter().copied());
+                loops.push(pipe_loop);
             }
-        } else {
-            start_pipe.shape = 'F';
         }
     }
+    loops
+}
 
-    let mut steps = 1;
+// The tiles of a loop, in order round it from where it was traced
+struct PipeLoop {
+    vertices: Vec<(usize, usize)>
+}
+impl PipeLoop {
+    fn trace(pipes: &PipeMap, start: (usize, usize)) -> Option<Self> {
+        Self::trace_from(pipes, start, pipes.at(start).exits?)
+    }
 
-    loop {
-        for &loc in search_front.iter() {
-            let pipe = pipes.at_mut(loc);
-            if let Some(dist) = pipe.dist_from_s {
-                assert_eq!(dist, steps);
-                return steps;
+    // Follows the pipes out of one of `start_exits`, failing unless every pipe joins up with the
+    // last and the path comes back in through the other
+    fn trace_from(pipes: &PipeMap, start: (usize, usize), start_exits: [(usize, usize); 2]) -> Option<Self> {
+        let mut vertices = vec![start];
+        let mut previous = start;
+        let mut current = start_exits[0];
+        while current != start {
+            let pipe = pipes.at(current);
+            if !pipe.exits_to(previous) {
+                return None;
             }
-            pipe.dist_from_s = Some(steps);
+            vertices.push(current);
+            let exits = pipe.exits.unwrap();
+            let next = if exits[0] == previous { exits[1] } else { exits[0] };
+            previous = current;
+            current = next;
         }
 
-        search_front = search_front.iter().filter_map(|&loc| {
-            if let Some(exits) = pipes.at(loc).exits {
-                if pipes.at(exits[0]).dist_from_s.is_none() {
-                    Some(exits[0])
-                } else if pipes.at(exits[1]).dist_from_s.is_none() {
-                    Some(exits[1])
-                } else {
-                    None
+        (previous == start_exits[1]).then_some(Self { vertices })
+    }
+
+    // The tile halfway round, and how many steps it is from the start either way
+    fn farthest(&self) -> (usize, (usize, usize)) {
+        (self.len() / 2, self.vertices[self.len() / 2])
+    }
+
+    fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    // Twice the area inside the loop's centre line, by the shoelace formula
+    fn double_area(&self) -> usize {
+        let twice_signed = self.vertices.iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(&(y1, x1), &(y2, x2))| x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64)
+            .sum::<i64>();
+        twice_signed.unsigned_abs() as usize
+    }
+
+    // Pick's theorem: area = interior + boundary / 2 - 1, where every loop tile is a boundary point
+    fn enclosed_count(&self) -> usize {
+        (self.double_area() + 2 - self.len()) / 2
+    }
+
+    // Scans each row, flipping between outside and inside whenever the loop crosses northwards
+    fn enclosed_cells(&self, pipes: &PipeMap) -> HashSet<(usize, usize)> {
+        let on_loop = self.vertices.iter().copied().collect::<HashSet<(usize, usize)>>();
+        let mut enclosed = HashSet::new();
+        for row in 0..pipes.height() {
+            let mut inside = false;
+            for col in 0..pipes.width() {
+                if on_loop.contains(&(row, col)) {
+                    if matches!(pipes.at((row, col)).shape, '|' | 'L' | 'J') {
+                        inside = !inside;
+                    }
+                } else if inside {
+                    enclosed.insert((row, col));
                 }
-            } else {
-                None
             }
-        }).collect();
-
-        if search_front.is_empty() { panic!() }
-        steps += 1;
+        }
+        enclosed
     }
 }
 
-fn part_two(pipes: &mut PipeMap) -> usize {
+fn part_two(pipes: &PipeMap, start: (usize, usize)) -> usize {
+    PipeLoop::trace(pipes, start).unwrap().enclosed_count()
+}
+
+// The original ray-casting count, kept to cross-check the shoelace one
+fn ray_cast_count(pipes: &mut PipeMap) -> usize {
     for row in pipes.pipes.iter_mut() {
         for cell in row.iter_mut() {
             if cell.dist_from_s.is_none() {
@@ -199,14 +387,12 @@ fn part_two(pipes: &mut PipeMap) -> usize {
     for row  in 0..pipes.height() {
         for col in 0..pipes.width() {
             if pipes.at((row, col)).shape == '.' {
-                print!("({}, {})", row, col);
                 let inside = ray_cast_inside(pipes, (row, col));
                 pipes.at_mut((row, col)).inside = Some(inside);
 
                 if inside {
                     count += 1;
                 }
-                println!(": {}", inside);
             }
         }
     }
@@ -270,3 +456,157 @@ fn ray_cast_inside(pipes: &PipeMap, mut from: (usize, usize)) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCLOSED_EXAMPLES: [(&str, usize); 3] = [
+        ("...........
+.S-------7.
+.|F-----7|.
+.||.....||.
+.||.....||.
+.|L-7.F-J|.
+.|..|.|..|.
+.L--J.L--J.
+...........", 4),
+        (".F----7F7F7F7F-7....
+.|F--7||||||||FJ....
+.||.FJ||||||||L7....
+FJL7L7LJLJ||LJ.L-7..
+L--J.L7...LJS7F-7L7.
+....F-J..F7FJ|L7L7L7
+....L7.F7||L7|.L7L7|
+.....|FJLJ|FJ|F7|.LJ
+....FJL-7.||.||||...
+....L---J.LJ.LJLJ...", 8),
+        ("FF7FSF7F7F7F7F7F---7
+L|LJ||||||||||||F--J
+FL-7LJLJ||||||LJL-77
+F--JF--7||LJLJ7F7FJ-
+L---JF-JLJ.||-FJLJJ7
+|F|F-JF---7F7-L7L|7|
+|FFJF7L7F-JF7|JL---7
+7-L-JL7||F7|L7F-7F7|
+L.L7LFJ|||||FJL7||LJ
+L7JLJL-JLJLJL--JLJ.L", 10)
+    ];
+
+    #[test]
+    fn test_enclosed_counts_agree() {
+        for (data, expected) in ENCLOSED_EXAMPLES {
+            let (mut pipes, start) = parse_data(data);
+            part_one(&mut pipes, start).unwrap();
+            assert_eq!(part_two(&pipes, start), expected);
+
+            let pipe_loop = PipeLoop::trace(&pipes, start).unwrap();
+            assert_eq!(pipe_loop.enclosed_cells(&pipes).len(), expected);
+            assert_eq!(ray_cast_count(&mut pipes), expected);
+        }
+    }
+
+    #[test]
+    fn test_farthest_tile() {
+        let (mut pipes, start) = parse_data(".....
+.S-7.
+.|.|.
+.L-J.
+.....");
+        assert_eq!(part_one(&mut pipes, start).unwrap(), (4, (3, 3)));
+
+        // S on the left edge
+        let (mut pipes, start) = parse_data("..F7.
+.FJ|.
+SJ.L7
+|F--J
+LJ...");
+        assert_eq!(part_one(&mut pipes, start).unwrap(), (8, (2, 4)));
+    }
+
+    #[test]
+    fn test_start_on_the_edge() {
+        let (mut pipes, start) = parse_data("S7
+LJ");
+        assert_eq!(part_one(&mut pipes, start).unwrap(), (2, (1, 1)));
+        assert_eq!(pipes.at(start).shape, 'F');
+
+        let (mut pipes, start) = parse_data("F7
+SJ");
+        assert_eq!(part_one(&mut pipes, start).unwrap(), (2, (0, 1)));
+        assert_eq!(pipes.at(start).shape, 'L');
+    }
+
+    #[test]
+    fn test_unresolvable_start() {
+        let (pipes, start) = parse_data("S-
+..");
+        assert!(matches!(resolve_start(&pipes, start), Err(StartError::NoLoop)));
+
+        // S closes a loop to the north-west as J, or to the south-east as F
+        let (mut pipes, start) = parse_data("F7.
+LS7
+.LJ");
+        assert!(matches!(resolve_start(&pipes, start), Err(StartError::Ambiguous(shapes)) if shapes == vec!['J', 'F']));
+        assert!(part_one(&mut pipes, start).is_err());
+    }
+
+    #[test]
+    fn test_independent_loops() {
+        let (pipes, _) = parse_data("F--7.F7
+|F7|.LJ
+|LJ|...
+L--J...");
+        let loops = find_loops(&pipes).iter()
+            .map(|pipe_loop| (pipe_loop.vertices[0], pipe_loop.len(), pipe_loop.enclosed_count()))
+            .collect::<Vec<_>>();
+        assert_eq!(loops, vec![((0, 0), 12, 4), ((0, 5), 4, 0), ((1, 1), 4, 0)]);
+    }
+
+    // A square loop with a stray pipe inside it
+    fn rendered_example() -> PipeMap {
+        let (mut pipes, start) = parse_data(".....
+.S-7.
+.|-|.
+.L-J.
+.....");
+        part_one(&mut pipes, start).unwrap();
+        let pipe_loop = PipeLoop::trace(&pipes, start).unwrap();
+        pipes.mark_enclosed(&pipe_loop);
+        pipes
+    }
+
+    #[test]
+    fn test_render() {
+        let rendered = rendered_example().to_string();
+        let cells = rendered.lines().map(|line| {
+            line.split("\x1b[0m")
+                .filter(|cell| !cell.is_empty())
+                .map(|cell| cell.trim_start_matches("\x1b[").split_once('m').unwrap())
+                .collect::<Vec<(&str, &str)>>()
+        }).collect::<Vec<_>>();
+
+        let glyphs = cells.iter()
+            .map(|row| row.iter().map(|(_, glyph)| *glyph).collect::<String>())
+            .collect::<Vec<String>>();
+        assert_eq!(glyphs, vec!["·····", "·┌─┐·", "·│─│·", "·└─┘·", "·····"]);
+
+        let styles = cells.iter().map(|row| row.iter().map(|(style, _)| *style).collect::<Vec<&str>>()).collect::<Vec<_>>();
+        assert_eq!(styles[0], vec!["0;34"; 5]);
+        assert_eq!(styles[1], vec!["0;34", "1", "1", "1", "0;34"]);
+        // The stray pipe is dimmed, and inside the loop
+        assert_eq!(styles[2], vec!["0;34", "1", "2;32", "1", "0;34"]);
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = rendered_example().to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"50\" height=\"50\">"));
+        // Every tile off the loop gets a background, and every pipe a line per exit
+        assert_eq!(svg.matches("<rect ").count(), 17);
+        assert_eq!(svg.matches("fill=\"palegreen\"").count(), 1);
+        assert_eq!(svg.matches("<line ").count(), 18);
+        assert_eq!(svg.matches("stroke=\"black\"").count(), 16);
+        assert_eq!(svg.matches("stroke=\"grey\"").count(), 2);
+    }
+}
@@ -1,5 +1,129 @@
+use std::{cmp::Ordering, collections::BTreeMap, env, fmt, fs};
+
 use input_curler::input_for;
 
+#[derive(Debug, Clone, Copy)]
+enum Metric {
+    Manhattan,
+    Chebyshev,
+    Euclidean
+}
+impl Metric {
+    fn distance(&self, (y1, x1): (u128, u128), (y2, x2): (u128, u128)) -> Distance {
+        let (dy, dx) = (y1.abs_diff(y2), x1.abs_diff(x2));
+        match self {
+            Metric::Manhattan => Distance::Exact(dy + dx),
+            Metric::Chebyshev => Distance::Exact(dy.max(dx)),
+            // Squaring could overflow for huge expansions, so stay in floating point
+            Metric::Euclidean => Distance::Approximate((dy as f64).hypot(dx as f64))
+        }
+    }
+}
+
+// Manhattan and Chebyshev distances are whole numbers and stay exact however far apart galaxies are
+#[derive(Debug, Clone, Copy)]
+enum Distance {
+    Exact(u128),
+    Approximate(f64)
+}
+impl Distance {
+    // Which `width` wide band of distances this falls in, counting from 0
+    fn band(&self, width: u128) -> u128 {
+        match *self {
+            Distance::Exact(distance) => distance / width,
+            Distance::Approximate(distance) => (distance / width as f64).floor() as u128
+        }
+    }
+}
+impl PartialEq for Distance {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Distance {}
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (*self, *other) {
+            (Distance::Exact(a), Distance::Exact(b)) => a.cmp(&b),
+            (Distance::Exact(a), Distance::Approximate(b)) => (a as f64).total_cmp(&b),
+            (Distance::Approximate(a), Distance::Exact(b)) => a.total_cmp(&(b as f64)),
+            (Distance::Approximate(a), Distance::Approximate(b)) => a.total_cmp(&b)
+        }
+    }
+}
+impl fmt::Display for Distance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Distance::Exact(distance) => write!(f, "{}", distance),
+            Distance::Approximate(distance) => write!(f, "{}", distance)
+        }
+    }
+}
+
+// Galaxies at their expanded positions, numbered from 0 in reading order
+struct Universe {
+    galaxies: Vec<(u128, u128)>
+}
+impl Universe {
+    fn expand(galaxies: &[(u64, u64)], row_factor: u128, col_factor: u128) -> Self {
+        let rows = galaxies.iter().map(|&(y, _)| y).collect::<Vec<u64>>();
+        let cols = galaxies.iter().map(|&(_, x)| x).collect::<Vec<u64>>();
+        let galaxies = expand_axis(&rows, row_factor).into_iter().zip(expand_axis(&cols, col_factor)).collect();
+        Self { galaxies }
+    }
+
+    fn distance(&self, metric: Metric, from: usize, to: usize) -> Distance {
+        metric.distance(self.galaxies[from], self.galaxies[to])
+    }
+
+    fn pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.galaxies.len()).flat_map(move |from| (from + 1..self.galaxies.len()).map(move |to| (from, to)))
+    }
+
+    // For each galaxy, the closest other one (the first, on a tie) and how far away it is
+    fn nearest_neighbours(&self, metric: Metric) -> Vec<Option<(usize, Distance)>> {
+        (0..self.galaxies.len()).map(|from| {
+            (0..self.galaxies.len())
+                .filter(|&to| to != from)
+                .map(|to| (to, self.distance(metric, from, to)))
+                .min_by_key(|&(_, distance)| distance)
+        }).collect()
+    }
+
+    fn farthest_pair(&self, metric: Metric) -> Option<((usize, usize), Distance)> {
+        self.pairs()
+            .map(|(from, to)| ((from, to), self.distance(metric, from, to)))
+            .max_by_key(|&(_, distance)| distance)
+    }
+
+    // How many pairs fall into each `band_width` wide band of distances, keyed by band number
+    fn histogram(&self, metric: Metric, band_width: u128) -> BTreeMap<u128, usize> {
+        let mut bands = BTreeMap::new();
+        for (from, to) in self.pairs() {
+            *bands.entry(self.distance(metric, from, to).band(band_width)).or_default() += 1;
+        }
+        bands
+    }
+
+    fn distance_matrix_csv(&self, metric: Metric) -> String {
+        let count = self.galaxies.len();
+        let mut csv = String::new();
+        csv.push_str(&(0..count).fold("galaxy".to_string(), |line, to| format!("{},{}", line, to + 1)));
+        csv.push('\n');
+        for from in 0..count {
+            let line = (0..count).fold((from + 1).to_string(), |line, to| format!("{},{}", line, self.distance(metric, from, to)));
+            csv.push_str(&line);
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
 fn main() {
     let data = input_for(11).unwrap();
 //     let data = "...#......
@@ -14,10 +138,55 @@ fn main() {
 // #...#.....".to_string();
     let galaxies = parse_data(&data);
 
-    let answer_one = total_distance(&galaxies, 1);
-    println!("Part one: {}", answer_one);
-    let answer_one = total_distance(&galaxies, 999999);
+    let answer_one = total_distance(&galaxies, 2);
     println!("Part one: {}", answer_one);
+    let answer_two = total_distance(&galaxies, 1_000_000);
+    println!("Part two: {}", answer_two);
+
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let flag_value = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|ix| args.get(ix + 1));
+    let metric = match flag_value("--metric").map(String::as_str) {
+        None | Some("manhattan") => Metric::Manhattan,
+        Some("chebyshev") => Metric::Chebyshev,
+        Some("euclidean") => Metric::Euclidean,
+        Some(unknown) => panic!("Unknown metric {}", unknown)
+    };
+    // Either one factor for both axes, or rows:cols
+    let (row_factor, col_factor) = flag_value("--expansion").map_or((2, 2), |factors| {
+        let factors = factors.split(':').map(|f| f.parse::<u128>().unwrap()).collect::<Vec<u128>>();
+        (factors[0], *factors.get(1).unwrap_or(&factors[0]))
+    });
+    if row_factor < 1 || col_factor < 1 {
+        println!("Expansion factors must be at least 1");
+        return;
+    }
+    let universe = Universe::expand(&galaxies, row_factor, col_factor);
+
+    if args.iter().any(|arg| arg == "--nearest") {
+        for (ix, nearest) in universe.nearest_neighbours(metric).into_iter().enumerate() {
+            if let Some((to, distance)) = nearest {
+                println!("{} -> {}: {}", ix + 1, to + 1, distance);
+            }
+        }
+    }
+    if args.iter().any(|arg| arg == "--farthest") {
+        if let Some(((from, to), distance)) = universe.farthest_pair(metric) {
+            println!("Farthest: {} and {}, {} apart", from + 1, to + 1, distance);
+        }
+    }
+    if let Some(width) = flag_value("--histogram") {
+        let width = width.parse::<u128>().unwrap();
+        if width == 0 {
+            println!("Histogram bands must be at least 1 wide");
+            return;
+        }
+        for (band, count) in universe.histogram(metric, width) {
+            println!("{}..{}: {}", band * width, (band + 1) * width, count);
+        }
+    }
+    if let Some(path) = flag_value("--csv") {
+        fs::write(path, universe.distance_matrix_csv(metric)).unwrap();
+    }
 }
 
 fn parse_data(data: &str) -> Vec<(u64, u64)> {
@@ -34,28 +203,88 @@ fn parse_data(data: &str) -> Vec<(u64, u64)> {
     galaxies
 }
 
-fn total_distance(galaxies: &[(u64, u64)], expansion: u64) -> u64 {
-    let mut empty_cols = Vec::<u64>::new();
-    for col in 0..galaxies.iter().map(|&(_, x)| x).max().unwrap() {
-        if !galaxies.iter().any(|&(_, x)| x == col) {
-            empty_cols.push(col);
-        }
+// Where each coordinate ends up once every line with no galaxy in it is `factor` lines wide. A
+// prefix sum over the lines counts the empty ones before each coordinate.
+fn expand_axis(coords: &[u64], factor: u128) -> Vec<u128> {
+    assert!(factor >= 1, "Empty lines can't shrink to nothing");
+    let len = coords.iter().max().map_or(0, |&max| max as usize + 1);
+    let mut occupied = vec![false; len];
+    for &coord in coords {
+        occupied[coord as usize] = true;
     }
-    let mut empty_rows = Vec::<u64>::new();
-    for row in 0..galaxies.iter().map(|&(y, _)| y).max().unwrap() {
-        if !galaxies.iter().any(|&(y, _)| y == row) {
-            empty_rows.push(row);
+    let mut empties_before = Vec::with_capacity(len);
+    let mut empties = 0u128;
+    for is_occupied in occupied {
+        empties_before.push(empties);
+        if !is_occupied {
+            empties += 1;
         }
     }
 
+    coords.iter().map(|&coord| coord as u128 + empties_before[coord as usize] * (factor - 1)).collect()
+}
+
+// Sum of |a - b| over every pair: once sorted, each coordinate is larger than all those before it
+fn pairwise_distance_sum(mut coords: Vec<u128>) -> u128 {
+    coords.sort_unstable();
     let mut total = 0;
-    for (ix, g1) in galaxies.iter().enumerate() {
-        for g2 in galaxies.iter().skip(ix) {
-            total += g1.0.abs_diff(g2.0) + g1.1.abs_diff(g2.1);
-            let empties = empty_cols.iter().filter(|col| (g1.1..g2.1).contains(col) || (g2.1..g1.1).contains(col)).count() +
-                empty_rows.iter().filter(|row| (g1.0..g2.0).contains(row) || (g2.0..g1.0).contains(row)).count();
-            total += empties as u64 * expansion;
-        }
+    let mut sum_before = 0;
+    for (ix, &coord) in coords.iter().enumerate() {
+        total += coord * ix as u128 - sum_before;
+        sum_before += coord;
     }
     total
 }
+
+fn total_distance(galaxies: &[(u64, u64)], factor: u128) -> u128 {
+    let rows = galaxies.iter().map(|&(y, _)| y).collect::<Vec<u64>>();
+    let cols = galaxies.iter().map(|&(_, x)| x).collect::<Vec<u64>>();
+    pairwise_distance_sum(expand_axis(&rows, factor)) + pairwise_distance_sum(expand_axis(&cols, factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "...#......
+.......#..
+#.........
+..........
+......#...
+.#........
+.........#
+..........
+.......#..
+#...#.....";
+
+    #[test]
+    fn test_total_distance() {
+        let galaxies = parse_data(EXAMPLE);
+        assert_eq!(total_distance(&galaxies, 2), 374);
+        assert_eq!(total_distance(&galaxies, 10), 1030);
+        assert_eq!(total_distance(&galaxies, 100), 8410);
+    }
+
+    #[test]
+    fn test_exact_distances_at_huge_expansion() {
+        // Galaxy 1 is at (0, 3) and galaxy 3 at (2, 0), with an empty column and no empty rows between them
+        let factor = 1_000_000_000_000_000_001;
+        let universe = Universe::expand(&parse_data(EXAMPLE), 1, factor);
+        assert_eq!(universe.distance(Metric::Manhattan, 0, 2), Distance::Exact(2 + 2 + factor));
+        assert_eq!(universe.distance(Metric::Chebyshev, 0, 2), Distance::Exact(2 + factor));
+        assert!(universe.distance_matrix_csv(Metric::Manhattan).contains(&format!(",{},", 4 + factor)));
+    }
+
+    #[test]
+    fn test_queries() {
+        let universe = Universe::expand(&parse_data(EXAMPLE), 2, 2);
+        let sum = universe.pairs().map(|(from, to)| match universe.distance(Metric::Manhattan, from, to) {
+            Distance::Exact(distance) => distance,
+            Distance::Approximate(_) => unreachable!()
+        }).sum::<u128>();
+        assert_eq!(sum, 374);
+        assert_eq!(universe.histogram(Metric::Manhattan, 5).values().sum::<usize>(), 36);
+        assert_eq!(universe.nearest_neighbours(Metric::Euclidean)[7], Some((8, Distance::Approximate(5.0))));
+        assert_eq!(universe.farthest_pair(Metric::Chebyshev), Some(((5, 7), Distance::Exact(12))));
+    }
+}
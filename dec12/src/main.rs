@@ -1,79 +1,194 @@
-use std::{collections::HashMap, iter};
-use itertools::{intersperse_with, Itertools};
-
 use input_curler::input_for;
 
-type History = HashMap<(String, Vec<usize>, bool), u64>;
+mod nonogram;
+mod unfolding;
 
-fn main() {
-    let data = input_for(12).unwrap();
+use std::fmt::Display;
 
-    let rows = data.lines().map(|line| {
-        let (pattern, groups) = line.split_once(' ').unwrap();
-        let group_counts = groups.split(',').map(|n| n.parse::<usize>().unwrap());
-        (pattern, group_counts.collect::<Vec<usize>>())
-    });
+use num::BigUint;
+use nonogram::{Nonogram, Solutions};
+use unfolding::{Count, Modular, SpringRow};
 
-    let mut history = History::new();
-    let answer_one: u64 = rows.clone().map(|row|
-        count_possibilities(row.0, &row.1.clone(), false, &mut history)
-    ).sum();
-    println!("Part one: {}", answer_one);
+// Counts the ways a row's unknown springs can be filled in. The two rows of the table are kept
+// between calls, so once they're big enough counting allocates nothing (for fixed-size counts).
+struct SpringCounter<C = u64> {
+    // ways[p]: how many ways the remaining groups fit into pattern[p..], for the group being placed
+    current: Vec<C>,
+    // The same, for the group after it
+    next: Vec<C>,
+    zero: C,
+    one: C
+}
+impl SpringCounter {
+    fn new() -> Self {
+        Self::with_unit(0, 1)
+    }
+}
+impl<C: Count> SpringCounter<C> {
+    // Counts with any type that can be added up, given what its zero and one are
+    fn with_unit(zero: C, one: C) -> Self {
+        Self { current: vec![], next: vec![], zero, one }
+    }
+
+    fn count(&mut self, pattern: &[u8], groups: &[usize]) -> C {
+        self.next.clear();
+        self.next.resize(pattern.len() + 1, self.zero.clone());
+        fill_final_row(pattern, &mut self.next, &self.zero, &self.one);
+        for &group in groups.iter().rev() {
+            self.current.clear();
+            self.current.resize(pattern.len() + 1, self.zero.clone());
+            fill_row(pattern, group, &self.next, &mut self.current, &self.zero);
+            std::mem::swap(&mut self.current, &mut self.next);
+        }
+        self.next[0].clone()
+    }
+}
+
+// With every group placed, the rest of the row fits only if it has no damaged springs left
+fn fill_final_row<C: Count>(pattern: &[u8], ways: &mut [C], zero: &C, one: &C) {
+    ways[pattern.len()] = one.clone();
+    for p in (0..pattern.len()).rev() {
+        ways[p] = if pattern[p] == b'#' { zero.clone() } else { ways[p + 1].clone() };
+    }
+}
+
+// Either the spring at p is operational and the group starts later, or the group starts at p:
+// `group` springs that could all be damaged, followed by the row's end or one that could be working
+fn fill_row<C: Count>(pattern: &[u8], group: usize, next: &[C], ways: &mut [C], zero: &C) {
+    let len = pattern.len();
+    // How many springs from p on could be damaged, without a gap
+    let mut run = 0;
+    ways[len] = zero.clone();
+    for p in (0..len).rev() {
+        run = if pattern[p] == b'.' { 0 } else { run + 1 };
+        let skip = if pattern[p] == b'#' { zero.clone() } else { ways[p + 1].clone() };
+        let end = p + group;
+        let place = if run >= group && (end == len || pattern[end] != b'#') {
+            next[(end + 1).min(len)].clone()
+        } else {
+            zero.clone()
+        };
+        ways[p] = skip + place;
+    }
+}
+
+fn count_possibilities(pattern: &str, groups: &[usize]) -> u64 {
+    SpringCounter::new().count(pattern.as_bytes(), groups)
+}
 
-    let answer_two: u64 = rows.map(|row| {
-        let pattern = iter::repeat(row.0).take(5).intersperse("?").collect::<String>();
-        let group_counts = row.1.repeat(5);
-        count_possibilities(&pattern, &group_counts, false, &mut history)
-    }).sum();
-    println!("Part two: {}", answer_two);
+// Every way of filling in a row, found lazily. The full counting table steers the search, so it
+// never follows a branch that can't be completed.
+struct Arrangements {
+    pattern: Vec<u8>,
+    groups: Vec<usize>,
+    // table[g][p]: how many ways groups g.. fit into pattern[p..]
+    table: Vec<Vec<u64>>,
+    // Partial rows still to be finished: where they're up to, the next group, and the row so far
+    stack: Vec<(usize, usize, Vec<u8>)>
 }
+impl Arrangements {
+    fn new(pattern: &str, groups: &[usize]) -> Self {
+        let pattern = pattern.as_bytes().to_vec();
+        let mut table = vec![vec![0; pattern.len() + 1]; groups.len() + 1];
+        fill_final_row(&pattern, &mut table[groups.len()], &0, &1);
+        for g in (0..groups.len()).rev() {
+            let (current, next) = table.split_at_mut(g + 1);
+            fill_row(&pattern, groups[g], &next[0], &mut current[g], &0);
+        }
 
-fn count_possibilities(pattern: &str, groups: &[usize], in_run: bool, history: &mut History) -> u64 {
-    if let Some(c) = history.get(&(pattern.to_string(), groups.to_vec(), in_run)) {
-        return *c;
+        let stack = if table[0][0] > 0 { vec![(0, 0, vec![])] } else { vec![] };
+        Self { pattern, groups: groups.to_vec(), table, stack }
     }
+}
+impl Iterator for Arrangements {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let len = self.pattern.len();
+        while let Some((p, g, mut row)) = self.stack.pop() {
+            if g == self.groups.len() {
+                row.resize(len, b'.');
+                return Some(String::from_utf8(row).unwrap());
+            }
 
-    let mut groups_vec = groups.to_vec();
-    let mut group_counts = groups_vec.as_mut_slice();
-    let count = match pattern.chars().next() {
-        Some('.') => {
-            if in_run && group_counts[0] > 0 {
-                0
-            } else {
-                if !group_counts.is_empty() && group_counts[0] == 0 {
-                    group_counts = &mut group_counts[1..];
+            let group = self.groups[g];
+            if self.pattern[p] != b'#' && self.table[g][p + 1] > 0 {
+                let mut skipped = row.clone();
+                skipped.push(b'.');
+                self.stack.push((p + 1, g, skipped));
+            }
+            let end = p + group;
+            let fits = end <= len
+                && self.pattern[p..end].iter().all(|&c| c != b'.')
+                && (end == len || self.pattern[end] != b'#');
+            if fits && self.table[g + 1][(end + 1).min(len)] > 0 {
+                row.extend(std::iter::repeat_n(b'#', group));
+                if end < len {
+                    row.push(b'.');
                 }
-                count_possibilities(&pattern[1..], group_counts, false, history)
+                self.stack.push(((end + 1).min(len), g + 1, row));
+            }
+        }
+        None
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if let Some(path) = args.iter().position(|arg| arg == "--nonogram").and_then(|ix| args.get(ix + 1)) {
+        let puzzle = Nonogram::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        match puzzle.solve() {
+            Solutions::None => println!("No solution"),
+            Solutions::Unique(grid) => print!("Unique solution:\n{}", nonogram::render(&grid)),
+            Solutions::Multiple(first, second) => {
+                print!("Multiple solutions, including:\n{}\nand:\n{}", nonogram::render(&first), nonogram::render(&second));
             }
-        },
-        Some('#') => {
-            if group_counts.is_empty() || group_counts[0] == 0 {
-                0
-            } else {
-                group_counts[0] -= 1;
-                count_possibilities(&pattern[1..], group_counts, true, history)
+        }
+        return;
+    }
+
+    let data = input_for(12).unwrap();
+
+    let rows = data.lines().map(SpringRow::parse).collect::<Vec<SpringRow>>();
+
+    if let Some(limit) = args.iter().position(|arg| arg == "--show").and_then(|ix| args.get(ix + 1)) {
+        let limit = limit.parse::<usize>().unwrap();
+        for row in &rows {
+            println!("{} {:?}: {} ways", row.pattern, row.groups, count_possibilities(&row.pattern, &row.groups));
+            for arrangement in Arrangements::new(&row.pattern, &row.groups).take(limit) {
+                println!("  {}", arrangement);
             }
-        },
-        Some('?') => {
-            let mut pattern_ok = ".".to_string();
-            pattern_ok.push_str(&pattern[1..]);
-            let mut pattern_broken = "#".to_string();
-            pattern_broken.push_str(&pattern[1..]);
-            count_possibilities(pattern_ok.as_str(), group_counts, in_run, history)
-                + count_possibilities(pattern_broken.as_str(), group_counts, in_run, history)
-        },
-        None => {
-            if group_counts.is_empty() || (group_counts.len() == 1 && group_counts[0] == 0) {
-                1
-            } else {
-                0
+        }
+        return;
+    }
+
+    let mut counter = SpringCounter::new();
+    let answer_one: u64 = rows.iter().map(|row| row.count(&mut counter)).sum();
+    println!("Part one: {}", answer_one);
+
+    let flag_value = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|ix| args.get(ix + 1));
+    let factor = flag_value("--unfold").map_or(5, |factor| factor.parse::<usize>().unwrap());
+    let separator = flag_value("--separator").map_or("?", String::as_str);
+    match flag_value("--counts").map(String::as_str) {
+        None | Some("u128") => unfolded_total(&rows, factor, separator, 0u128, 1),
+        Some("big") => unfolded_total(&rows, factor, separator, BigUint::from(0u32), BigUint::from(1u32)),
+        Some(modulus) => {
+            let modulus = modulus.strip_prefix("mod:").unwrap_or(modulus).parse::<u64>().unwrap();
+            if modulus < 2 {
+                println!("The modulus must be a prime, so at least 2");
+                return;
             }
-        },
-        _ => unreachable!()
-    };
+            unfolded_total(&rows, factor, separator, Modular::new(0, modulus), Modular::new(1, modulus))
+        }
+    }
+}
 
-    history.insert((pattern.to_string(), groups.to_vec(), in_run), count);
-    count
+fn unfolded_total<C: Count + Display>(rows: &[SpringRow], factor: usize, separator: &str, zero: C, one: C) {
+    let mut counter = SpringCounter::with_unit(zero.clone(), one.clone());
+    let total = rows.iter().fold(zero.clone(), |total, row|
+        total + row.count_unfolded(factor, separator, &mut counter)
+    );
+    println!("Part two: {}", total);
 }
 
 #[cfg(test)]
@@ -83,10 +198,9 @@ mod tests {
     #[test]
     fn test_when_no_unknown_and_matches() {
         let pattern = ".#..###.##..#";
-        let mut group_counts = vec![1, 3, 2, 1];
+        let group_counts = vec![1, 3, 2, 1];
 
-        let mut history = History::new();
-        let possibilities = count_possibilities(pattern, &group_counts, false, &mut history);
+        let possibilities = count_possibilities(pattern, &group_counts);
         assert_eq!(possibilities, 1);
     }
 
@@ -95,8 +209,7 @@ mod tests {
         let pattern = ".#..###.##..#";
         let group_counts = vec![1, 3, 3, 1];
 
-        let mut history = History::new();
-        let possibilities = count_possibilities(pattern, &group_counts, false, &mut history);
+        let possibilities = count_possibilities(pattern, &group_counts);
         assert_eq!(possibilities, 0);
     }
 
@@ -105,8 +218,7 @@ mod tests {
         let pattern = ".#..#?#.##..#";
         let group_counts = vec![1, 3, 2, 1];
 
-        let mut history = History::new();
-        let possibilities = count_possibilities(pattern, &group_counts, false, &mut history);
+        let possibilities = count_possibilities(pattern, &group_counts);
         assert_eq!(possibilities, 1);
     }
 
@@ -115,8 +227,7 @@ mod tests {
         let pattern = ".??";
         let group_counts = vec![1];
 
-        let mut history = History::new();
-        let possibilities = count_possibilities(pattern, &group_counts, false, &mut history);
+        let possibilities = count_possibilities(pattern, &group_counts);
         assert_eq!(possibilities, 2);
     }
 
@@ -125,8 +236,7 @@ mod tests {
         let pattern = ".??.??";
         let group_counts = vec![1, 1];
 
-        let mut history = History::new();
-        let possibilities = count_possibilities(pattern, &group_counts, false, &mut history);
+        let possibilities = count_possibilities(pattern, &group_counts);
         assert_eq!(possibilities, 4);
     }
 
@@ -135,8 +245,54 @@ mod tests {
         let pattern = "?###????????";
         let group_counts = vec![3, 2, 1];
 
-        let mut history = History::new();
-        let possibilities = count_possibilities(pattern, &group_counts, false, &mut history);
+        let possibilities = count_possibilities(pattern, &group_counts);
         assert_eq!(possibilities, 10);
     }
+
+    // Every way of replacing the '?'s, kept if its runs of '#' match the groups
+    fn brute_force(pattern: &str, groups: &[usize]) -> Vec<String> {
+        let unknowns = pattern.chars().filter(|&c| c == '?').count();
+        (0..1u32 << unknowns).filter_map(|mask| {
+            let mut bit = 0;
+            let row = pattern.chars().map(|c| if c != '?' { c } else {
+                bit += 1;
+                if mask & (1 << (bit - 1)) != 0 { '#' } else { '.' }
+            }).collect::<String>();
+            let runs = row.split('.').filter(|run| !run.is_empty()).map(str::len).collect::<Vec<usize>>();
+            (runs == groups).then_some(row)
+        }).collect()
+    }
+
+    #[test]
+    fn test_matches_brute_force_and_enumeration() {
+        let rows = [
+            (".#..###.##..#", vec![1, 3, 2, 1]),
+            (".??.??", vec![1, 1]),
+            ("???.###", vec![1, 1, 3]),
+            (".??..??...?##.", vec![1, 1, 3]),
+            ("?#?#?#?#?#?#?#?", vec![1, 3, 1, 6]),
+            ("????.#...#...", vec![4, 1, 1]),
+            ("????.######..#####.", vec![1, 6, 5]),
+            ("?###????????", vec![3, 2, 1]),
+            ("??????????", vec![]),
+            ("?", vec![1]),
+            ("#", vec![2])
+        ];
+        for (pattern, groups) in rows {
+            let mut expected = brute_force(pattern, &groups);
+            let mut enumerated = Arrangements::new(pattern, &groups).collect::<Vec<String>>();
+            expected.sort();
+            enumerated.sort();
+            assert_eq!(count_possibilities(pattern, &groups), expected.len() as u64, "{}", pattern);
+            assert_eq!(enumerated, expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_enumeration_is_lazy() {
+        let pattern = "?".repeat(60);
+        let shown = Arrangements::new(&pattern, &[1, 1, 1, 1, 1]).take(3).collect::<Vec<String>>();
+        assert_eq!(shown.len(), 3);
+        assert!(shown.iter().all(|row| row.len() == 60 && row.matches('#').count() == 5));
+    }
 }
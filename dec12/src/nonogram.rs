@@ -0,0 +1,221 @@
+use std::ops::{Add, Mul};
+
+use crate::SpringCounter;
+
+// Each cell is b'#' (filled), b'.' (empty) or b'?' (not yet known), as in the spring rows
+pub type Grid = Vec<Vec<u8>>;
+
+pub struct Nonogram {
+    pub rows: Vec<Vec<usize>>,
+    pub columns: Vec<Vec<usize>>
+}
+
+pub enum Solutions {
+    None,
+    Unique(Grid),
+    // Two of the solutions, of however many there are
+    Multiple(Grid, Grid)
+}
+
+struct Contradiction;
+
+// Whether a line can be filled in at all, which is all the solver needs to know. An exact count
+// would overflow on a wide line with lots of short runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Possible(bool);
+impl Add for Possible {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Possible(self.0 || other.0)
+    }
+}
+impl Mul for Possible {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Possible(self.0 && other.0)
+    }
+}
+
+type LineCounter = SpringCounter<Possible>;
+
+impl Nonogram {
+    // Reads the `.non` format: `key value` lines, with the clues on the lines after `rows` and
+    // `columns`, one line each, as comma-separated runs ("0" for an empty line)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut width = None;
+        let mut height = None;
+        let mut rows = vec![];
+        let mut columns = vec![];
+        let mut section: Option<&mut Vec<Vec<usize>>> = None;
+
+        for line in text.lines().map(str::trim) {
+            if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                section = None;
+                match key {
+                    "width" => width = Some(value.trim().parse::<usize>().map_err(|e| format!("Bad width: {}", e))?),
+                    "height" => height = Some(value.trim().parse::<usize>().map_err(|e| format!("Bad height: {}", e))?),
+                    "rows" => section = Some(&mut rows),
+                    "columns" => section = Some(&mut columns),
+                    // Titles, authors, goals and so on don't matter for solving
+                    _ => ()
+                }
+            } else if let Some(clues) = section.as_mut() {
+                if line.is_empty() {
+                    continue;
+                }
+                let clue = line.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|n| !n.is_empty())
+                    .map(|n| n.parse::<usize>().map_err(|e| format!("Bad clue {:?}: {}", line, e)))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                clues.push(clue.into_iter().filter(|&n| n > 0).collect());
+            }
+        }
+
+        if height.is_some_and(|height| height != rows.len()) {
+            return Err(format!("Expected {} rows, found {}", height.unwrap(), rows.len()));
+        }
+        if width.is_some_and(|width| width != columns.len()) {
+            return Err(format!("Expected {} columns, found {}", width.unwrap(), columns.len()));
+        }
+        Ok(Self { rows, columns })
+    }
+
+    pub fn solve(&self) -> Solutions {
+        let mut grid = vec![vec![b'?'; self.columns.len()]; self.rows.len()];
+        let mut solutions = vec![];
+        self.search(&mut grid, &mut LineCounter::with_unit(Possible(false), Possible(true)), &mut solutions);
+
+        let mut solutions = solutions.into_iter();
+        match (solutions.next(), solutions.next()) {
+            (None, _) => Solutions::None,
+            (Some(only), None) => Solutions::Unique(only),
+            (Some(first), Some(second)) => Solutions::Multiple(first, second)
+        }
+    }
+
+    // Propagates as far as possible, then guesses the first unknown cell each way. Stops once
+    // two solutions have turned up, as that's enough to know it isn't unique.
+    fn search(&self, grid: &mut Grid, counter: &mut LineCounter, solutions: &mut Vec<Grid>) {
+        if self.propagate(grid, counter).is_err() {
+            return;
+        }
+        let Some((row, col)) = (0..grid.len())
+            .flat_map(|row| (0..grid[row].len()).map(move |col| (row, col)))
+            .find(|&(row, col)| grid[row][col] == b'?')
+        else {
+            solutions.push(grid.clone());
+            return;
+        };
+
+        for guess in [b'#', b'.'] {
+            if solutions.len() >= 2 {
+                return;
+            }
+            let mut guessed = grid.clone();
+            guessed[row][col] = guess;
+            self.search(&mut guessed, counter, solutions);
+        }
+    }
+
+    // Solves each row and column on its own, over and over, until none of them changes
+    fn propagate(&self, grid: &mut Grid, counter: &mut LineCounter) -> Result<(), Contradiction> {
+        let mut dirty_rows = vec![true; self.rows.len()];
+        let mut dirty_columns = vec![true; self.columns.len()];
+        let mut line = vec![];
+        while dirty_rows.contains(&true) || dirty_columns.contains(&true) {
+            for row in 0..self.rows.len() {
+                if !std::mem::take(&mut dirty_rows[row]) {
+                    continue;
+                }
+                line.clone_from(&grid[row]);
+                for col in solve_line(&mut line, &self.rows[row], counter)? {
+                    grid[row][col] = line[col];
+                    dirty_columns[col] = true;
+                }
+            }
+            for col in 0..self.columns.len() {
+                if !std::mem::take(&mut dirty_columns[col]) {
+                    continue;
+                }
+                line.clear();
+                line.extend(grid.iter().map(|cells| cells[col]));
+                for row in solve_line(&mut line, &self.columns[col], counter)? {
+                    grid[row][col] = line[row];
+                    dirty_rows[row] = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Fills in every unknown cell that only has one possibility, returning which ones changed
+fn solve_line(line: &mut [u8], clue: &[usize], counter: &mut LineCounter) -> Result<Vec<usize>, Contradiction> {
+    if counter.count(line, clue) == Possible(false) {
+        return Err(Contradiction);
+    }
+    let mut changed = vec![];
+    for ix in 0..line.len() {
+        if line[ix] != b'?' {
+            continue;
+        }
+        line[ix] = b'#';
+        let filled = counter.count(line, clue);
+        line[ix] = b'.';
+        let empty = counter.count(line, clue);
+        line[ix] = match (filled, empty) {
+            (Possible(false), _) => b'.',
+            (_, Possible(false)) => b'#',
+            _ => b'?'
+        };
+        if line[ix] != b'?' {
+            changed.push(ix);
+        }
+    }
+    Ok(changed)
+}
+
+pub fn render(grid: &Grid) -> String {
+    grid.iter().map(|row| row.iter().map(|&cell| if cell == b'#' { '█' } else { '·' }).collect::<String>() + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solutions() {
+        // A heart
+        let puzzle = "title \"Heart\"\nwidth 5\nheight 5\n\nrows\n1,1,1\n5\n5\n3\n1\n\ncolumns\n3\n3\n5\n3\n3\n";
+        match Nonogram::parse(puzzle).unwrap().solve() {
+            Solutions::Unique(grid) => assert_eq!(render(&grid), "█·█·█\n█████\n█████\n·███·\n··█··\n"),
+            _ => panic!("expected a unique solution")
+        }
+
+        let diagonals = "width 2\nheight 2\nrows\n1\n1\ncolumns\n1\n1\n";
+        assert!(matches!(Nonogram::parse(diagonals).unwrap().solve(), Solutions::Multiple(..)));
+
+        let impossible = "width 2\nheight 2\nrows\n2\n0\ncolumns\n0\n1\n";
+        assert!(matches!(Nonogram::parse(impossible).unwrap().solve(), Solutions::None));
+    }
+
+    #[test]
+    fn test_wide_lines() {
+        let mut counter = LineCounter::with_unit(Possible(false), Possible(true));
+        // About 1e20 ways to fill this in, too many for a u64, and no cell is certain
+        let clue = vec![1; 30];
+        let mut line = vec![b'?'; 100];
+        assert!(solve_line(&mut line, &clue, &mut counter).is_ok_and(|changed| changed.is_empty()));
+
+        // Just wide enough, so every cell is
+        let mut line = vec![b'?'; 59];
+        assert_eq!(solve_line(&mut line, &clue, &mut counter).ok().map(|changed| changed.len()), Some(59));
+        assert_eq!(line, "#.".repeat(29).into_bytes().into_iter().chain([b'#']).collect::<Vec<u8>>());
+
+        let mut line = vec![b'?'; 58];
+        assert!(solve_line(&mut line, &clue, &mut counter).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{collections::{hash_map::Entry, HashMap}, fmt, ops::{Add, Mul}};
+
+use crate::SpringCounter;
+
+// Where a scan along a row is between springs: on group g, having laid r of its damaged springs
+type State = (usize, usize);
+
+// Anything arrangements can be counted in: u64, u128, big integers or counts modulo a prime
+pub trait Count: Clone + Add<Output = Self> + Mul<Output = Self> {}
+impl<C: Clone + Add<Output = C> + Mul<Output = C>> Count for C {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modular {
+    value: u64,
+    modulus: u64
+}
+impl Modular {
+    pub fn new(value: u64, modulus: u64) -> Self {
+        assert!(modulus >= 2, "Counting modulo {} loses every count", modulus);
+        Self { value: value % modulus, modulus }
+    }
+}
+impl Add for Modular {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self { value: ((self.value as u128 + other.value as u128) % self.modulus as u128) as u64, ..self }
+    }
+}
+impl Mul for Modular {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self { value: ((self.value as u128 * other.value as u128) % self.modulus as u128) as u64, ..self }
+    }
+}
+impl fmt::Display for Modular {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
+    }
+}
+
+// Beyond this many copies, counting goes copy by copy rather than over the whole unfolded row
+const DIRECT_FACTOR_LIMIT: usize = 20;
+
+pub struct SpringRow {
+    pub pattern: String,
+    pub groups: Vec<usize>
+}
+impl SpringRow {
+    pub fn parse(line: &str) -> Self {
+        let (pattern, groups) = line.split_once(' ').unwrap();
+        Self {
+            pattern: pattern.to_string(),
+            groups: groups.split(',').map(|n| n.parse::<usize>().unwrap()).collect()
+        }
+    }
+
+    pub fn unfold(&self, factor: usize, separator: &str) -> Self {
+        Self {
+            pattern: vec![self.pattern.as_str(); factor].join(separator),
+            groups: self.groups.repeat(factor)
+        }
+    }
+
+    pub fn count<C: Count>(&self, counter: &mut SpringCounter<C>) -> C {
+        counter.count(self.pattern.as_bytes(), &self.groups)
+    }
+
+    pub fn count_unfolded<C: Count>(&self, factor: usize, separator: &str, counter: &mut SpringCounter<C>) -> C {
+        if factor <= DIRECT_FACTOR_LIMIT || self.groups.is_empty() {
+            self.unfold(factor, separator).count(counter)
+        } else {
+            self.transfer_count(factor, separator, &counter.zero, &counter.one)
+        }
+    }
+
+    // Scans the unfolded row a copy (the pattern plus the separator) at a time. What one copy
+    // does to a state only depends on g modulo the number of groups, so each copy's effect is the
+    // same transfer matrix, worked out once. States that can no longer fit the remaining groups
+    // into the rest of the row are dropped as the scan goes.
+    pub fn transfer_count<C: Count>(&self, factor: usize, separator: &str, zero: &C, one: &C) -> C {
+        let group_count = self.groups.len();
+        let total_groups = group_count * factor;
+        let group_sum = self.groups.iter().sum::<usize>();
+        let size = |g: usize| self.groups[g % group_count];
+        // The fewest springs needed for everything from state (g, r) on
+        let needed = |g: usize, r: usize| {
+            if g >= total_groups {
+                return 0;
+            }
+            let laid = (g / group_count) * group_sum + self.groups[..g % group_count].iter().sum::<usize>() + r;
+            group_sum * factor - laid + (total_groups - g - 1)
+        };
+
+        let copy = format!("{}{}", self.pattern, separator);
+        let full = TransferMatrix::new(copy.as_bytes(), &self.groups, one);
+        let last = TransferMatrix::new(self.pattern.as_bytes(), &self.groups, one);
+        let mut remaining = factor * self.pattern.len() + (factor - 1) * separator.len();
+
+        let mut states = HashMap::from([((0, 0), one.clone())]);
+        for copy_ix in 0..factor {
+            let (matrix, copy_len) = if copy_ix + 1 < factor { (&full, copy.len()) } else { (&last, self.pattern.len()) };
+            remaining -= copy_len;
+
+            let mut next = HashMap::new();
+            for (&(g, r), ways) in &states {
+                for ((advance, to_r), paths) in &matrix.moves[&(g % group_count, r)] {
+                    let (to_g, to_r) = (g + advance, *to_r);
+                    if to_g > total_groups || (to_g == total_groups && to_r > 0) || needed(to_g, to_r) > remaining {
+                        continue;
+                    }
+                    add_ways(&mut next, (to_g, to_r), ways.clone() * paths.clone());
+                }
+            }
+            states = next;
+        }
+
+        // Finished, or finishing the last group right at the end of the row
+        [(total_groups, 0), (total_groups - 1, size(total_groups - 1))]
+            .iter()
+            .filter_map(|state| states.get(state))
+            .fold(zero.clone(), |total, ways| total + ways.clone())
+    }
+}
+
+// For each state at the start of a stretch of springs, with g taken modulo the number of groups:
+// each state it can end in, as (groups moved on, springs laid), and how many ways there are to get there
+struct TransferMatrix<C> {
+    moves: HashMap<State, Vec<(State, C)>>
+}
+impl<C: Count> TransferMatrix<C> {
+    fn new(springs: &[u8], groups: &[usize], one: &C) -> Self {
+        let size = |g: usize| groups[g % groups.len()];
+        let mut moves = HashMap::new();
+        for (phase, &group) in groups.iter().enumerate() {
+            for laid in 0..=group {
+                let mut states = HashMap::from([((phase, laid), one.clone())]);
+                for &spring in springs {
+                    let mut next = HashMap::new();
+                    for (&(g, r), ways) in &states {
+                        if spring != b'#' {
+                            if r == 0 {
+                                add_ways(&mut next, (g, 0), ways.clone());
+                            } else if r == size(g) {
+                                add_ways(&mut next, (g + 1, 0), ways.clone());
+                            }
+                        }
+                        if spring != b'.' && r < size(g) {
+                            add_ways(&mut next, (g, r + 1), ways.clone());
+                        }
+                    }
+                    states = next;
+                }
+                let ends = states.into_iter().map(|((g, r), ways)| ((g - phase, r), ways)).collect();
+                moves.insert((phase, laid), ends);
+            }
+        }
+        Self { moves }
+    }
+}
+
+fn add_ways<C: Count>(states: &mut HashMap<State, C>, state: State, ways: C) {
+    match states.entry(state) {
+        Entry::Occupied(mut entry) => {
+            let total = entry.get().clone() + ways;
+            entry.insert(total);
+        },
+        Entry::Vacant(entry) => {
+            entry.insert(ways);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigUint;
+
+    use super::*;
+
+    #[test]
+    fn test_matches_direct_count() {
+        let example = "???.### 1,1,3\n.??..??...?##. 1,1,3\n?#?#?#?#?#?#?#? 1,3,1,6\n????.#...#... 4,1,1\n????.######..#####. 1,6,5\n?###???????? 3,2,1";
+        let rows = example.lines().map(SpringRow::parse).collect::<Vec<SpringRow>>();
+        let mut counter = SpringCounter::with_unit(0u128, 1);
+        for row in &rows {
+            for factor in 1..8 {
+                for separator in ["?", ".", "#", "", "?#"] {
+                    let direct = row.unfold(factor, separator).count(&mut counter);
+                    let transfer = row.transfer_count(factor, separator, &0, &1);
+                    assert_eq!(transfer, direct, "{} x{} with {:?}", row.pattern, factor, separator);
+                }
+            }
+        }
+        assert_eq!(rows.iter().map(|row| row.count_unfolded(5, "?", &mut counter)).sum::<u128>(), 525152);
+
+        let mut counter = SpringCounter::with_unit(Modular::new(0, 1_000_000_007), Modular::new(1, 1_000_000_007));
+        let big = rows[5].transfer_count(1000, "?", &BigUint::from(0u32), &BigUint::from(1u32));
+        let modular = rows[5].count_unfolded(1000, "?", &mut counter);
+        assert_eq!(modular, Modular::new((big % 1_000_000_007u32).try_into().unwrap(), 1_000_000_007));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_modulus_must_be_at_least_two() {
+        Modular::new(0, 1);
+    }
+}
@@ -1,3 +1,5 @@
+use std::env;
+
 use input_curler::input_for;
 
 fn main() {
@@ -18,92 +20,371 @@ fn main() {
 // #....#..#".to_string();
     let data = input_for(13).unwrap();
 
-    let patterns = data.split("\n\n");
+    let patterns = data.split("\n\n").map(Pattern::parse).collect::<Vec<Pattern>>();
 
-    let answer_one = part_one(patterns.clone());
+    let answer_one = part_one(&patterns);
     println!("Part one: {}", answer_one);
 
-    let answer_two = part_two(patterns.clone());
+    let answer_two = part_two(&patterns);
     println!("Part two: {}", answer_two);
+
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Some(budget) = args.iter().position(|arg| arg == "--smudges").and_then(|ix| args.get(ix + 1)) {
+        let budget = budget.parse::<u32>().unwrap();
+        for (ix, pattern) in patterns.iter().enumerate() {
+            for reflection in pattern.reflections(budget) {
+                println!("Pattern {}: {:?}, smudges at {:?}", ix + 1, reflection.mirror, reflection.smudges);
+            }
+        }
+    }
+    if let Some(budget) = args.iter().position(|arg| arg == "--symmetry").and_then(|ix| args.get(ix + 1)) {
+        let budget = budget.parse::<u32>().unwrap();
+        for (ix, pattern) in patterns.iter().enumerate() {
+            for found in pattern.symmetries(budget) {
+                println!("Pattern {}: {:?}, smudges at {:?}", ix + 1, found.symmetry, found.smudges);
+            }
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MirrorLoc {
     Row(u32),
     Column(u32)
 }
-
-fn part_one<'a, Iter>(patterns: Iter) -> u32
-where Iter: Iterator<Item = &'a str>
-{
-    patterns.map(|pattern| {
-        match find_reflection(pattern, false) {
-            MirrorLoc::Column(x) => x,
+impl MirrorLoc {
+    fn summary(&self) -> u32 {
+        match self {
+            MirrorLoc::Column(x) => *x,
             MirrorLoc::Row(y) => 100 * y
         }
-    }).sum()
+    }
 }
 
-fn part_two<'a, Iter>(patterns: Iter) -> u32
-where Iter: Iterator<Item = &'a str>
-{
-    patterns.map(|pattern| {
-        match find_reflection(pattern, true) {
-            MirrorLoc::Column(x) => x,
-            MirrorLoc::Row(y) => 100 * y
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Symmetry {
+    Mirror(MirrorLoc),
+    // A mirror line running down and to the right, through the cells where col - row = offset
+    Diagonal { offset: i64 },
+    // One running down and to the left, through the cells where row + col = sum
+    AntiDiagonal { sum: i64 },
+    // Centres are in half-cells, so (2, 3) is the middle of the edge between cells (1, 1) and (1, 2)
+    QuarterTurn { centre: (i64, i64) },
+    HalfTurn { centre: (i64, i64) }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SymmetryMatch {
+    symmetry: Symmetry,
+    // The fewest cells that need flipping to make the pattern symmetric
+    smudges: Vec<(usize, usize)>
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Reflection {
+    mirror: MirrorLoc,
+    // The (row, col) of each cell that doesn't match its image, taking the one above or left of
+    // the line; fixing either cell of the pair fixes the reflection
+    smudges: Vec<(usize, usize)>
+}
+
+// Each row and each column as a bitmask, with bit n set for a '#' n cells along
+struct Pattern {
+    rows: Vec<u64>,
+    columns: Vec<u64>
+}
+impl Pattern {
+    fn parse(pattern: &str) -> Self {
+        let width = pattern.lines().next().unwrap().len();
+        assert!(width <= 64 && pattern.lines().count() <= 64, "Patterns can be at most 64 by 64");
+
+        let mut rows = vec![];
+        let mut columns = vec![0; width];
+        for (y, line) in pattern.lines().enumerate() {
+            let mut row = 0;
+            for (x, cell) in line.bytes().enumerate() {
+                if cell == b'#' {
+                    row |= 1 << x;
+                    columns[x] |= 1 << y;
+                }
+            }
+            rows.push(row);
         }
-    }).sum()
+        Self { rows, columns }
+    }
+
+    // Every mirror line, vertical ones first, that needs at most `budget` smudges fixing
+    fn reflections(&self, budget: u32) -> Vec<Reflection> {
+        let columns = line_reflections(&self.columns, budget).into_iter().map(|(after, smudges)| Reflection {
+            mirror: MirrorLoc::Column(after as u32),
+            smudges: smudges.into_iter().map(|(col, row)| (row, col)).collect()
+        });
+        let rows = line_reflections(&self.rows, budget).into_iter().map(|(after, smudges)| Reflection {
+            mirror: MirrorLoc::Row(after as u32),
+            smudges
+        });
+        columns.chain(rows).collect()
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn cell(&self, row: usize, col: usize) -> bool {
+        self.rows[row] & (1 << col) != 0
+    }
+
+    // Every mirror line (straight or diagonal) and every rotation, about any centre, that needs at
+    // most `budget` smudges fixing. Cells whose image falls off the pattern are ignored, as the
+    // puzzle does for straight mirrors. A straight mirror always pairs up whole rows or columns,
+    // but a diagonal or rotation near a corner pairs up only a cell or two, which match by chance,
+    // so those have to keep more than half the pattern's cells on it.
+    fn symmetries(&self, budget: u32) -> Vec<SymmetryMatch> {
+        let mut matches = self.reflections(budget).into_iter().map(|reflection| SymmetryMatch {
+            symmetry: Symmetry::Mirror(reflection.mirror),
+            smudges: reflection.smudges
+        }).collect::<Vec<SymmetryMatch>>();
+
+        let (height, width) = (self.height() as i64, self.width() as i64);
+        let centres = (0..2 * height - 1).flat_map(|row| (0..2 * width - 1).map(move |col| (row, col)));
+        let candidates = (1 - height..width).map(|offset| Symmetry::Diagonal { offset })
+            .chain((0..height + width - 1).map(|sum| Symmetry::AntiDiagonal { sum }))
+            // A quarter turn only maps cells onto cells about a cell's centre or one of its corners
+            .chain(centres.clone().filter(|(row, col)| row % 2 == col % 2).map(|centre| Symmetry::QuarterTurn { centre }))
+            .chain(centres.map(|centre| Symmetry::HalfTurn { centre }));
+
+        for symmetry in candidates {
+            if let Some(smudges) = self.smudges_for(symmetry, budget) {
+                matches.push(SymmetryMatch { symmetry, smudges });
+            }
+        }
+        matches
+    }
+
+    // Groups the cells that the symmetry says must match, then takes the minority of each group
+    // (the earlier cells, on a tie) as the smudges
+    fn smudges_for(&self, symmetry: Symmetry, budget: u32) -> Option<Vec<(usize, usize)>> {
+        let (height, width) = (self.height(), self.width());
+        let mut groups = (0..height * width).collect::<Vec<usize>>();
+        let mut compared = false;
+        let mut overlap = 0;
+        for row in 0..height {
+            for col in 0..width {
+                let Some((image_row, image_col)) = symmetry.image((row, col)) else {
+                    continue;
+                };
+                if image_row < 0 || image_col < 0 || image_row >= height as i64 || image_col >= width as i64 {
+                    continue;
+                }
+                overlap += 1;
+                let (from, to) = (row * width + col, image_row as usize * width + image_col as usize);
+                if from != to {
+                    compared = true;
+                    let (from_root, to_root) = (find_root(&mut groups, from), find_root(&mut groups, to));
+                    groups[from_root.max(to_root)] = from_root.min(to_root);
+                }
+            }
+        }
+        if !compared || 2 * overlap <= height * width {
+            return None;
+        }
+
+        // Per group: its cells, as (filled, empty)
+        let mut members = vec![(vec![], vec![]); height * width];
+        for ix in 0..height * width {
+            let root = find_root(&mut groups, ix);
+            let cell = (ix / width, ix % width);
+            if self.cell(cell.0, cell.1) { members[root].0.push(cell) } else { members[root].1.push(cell) }
+        }
+        let mut smudges = vec![];
+        for (filled, empty) in members {
+            let minority = if filled.len() < empty.len() || (filled.len() == empty.len() && filled < empty) { filled } else { empty };
+            smudges.extend(minority);
+            if smudges.len() > budget as usize {
+                return None;
+            }
+        }
+        smudges.sort();
+        Some(smudges)
+    }
 }
 
-fn find_reflection(pattern: &str, smudge_allowed: bool) -> MirrorLoc {
-    if let Some(x) = find_col_reflection(pattern, smudge_allowed) {
-        MirrorLoc::Column(x as u32 + 1)
-    } else if let Some(y) = find_row_reflection(pattern, smudge_allowed) {
-        MirrorLoc::Row(y as u32 + 1)
-    } else {
-        panic!()
+impl Symmetry {
+    // Where a cell ends up; mirror lines between rows or columns are dealt with by `reflections`
+    fn image(&self, (row, col): (usize, usize)) -> Option<(i64, i64)> {
+        let (row, col) = (row as i64, col as i64);
+        match *self {
+            Symmetry::Mirror(_) => None,
+            Symmetry::Diagonal { offset } => Some((col - offset, row + offset)),
+            Symmetry::AntiDiagonal { sum } => Some((sum - col, sum - row)),
+            // Clockwise, which is enough: turning the other way gives the same groups of cells. The
+            // centre's halves share a parity (see `symmetries`), so both divisions are exact.
+            Symmetry::QuarterTurn { centre: (centre_row, centre_col) } =>
+                Some(((centre_row - centre_col) / 2 + col, (centre_row + centre_col) / 2 - row)),
+            Symmetry::HalfTurn { centre: (centre_row, centre_col) } => Some((centre_row - row, centre_col - col))
+        }
     }
 }
 
-fn find_row_reflection(pattern: &str, smudge_allowed: bool) -> Option<usize> {
-    let num_lines = pattern.lines().count();
-
-    (0..num_lines - 1).find(|&after_row| {
-        let mut inner_smudge_allowed = smudge_allowed;
-        (0..=after_row).all(|check_row| {
-            let matching_row = 2 * after_row - check_row + 1;
-            matching_row >= num_lines ||
-                patterns_match(
-                    pattern.lines().nth(check_row).unwrap(),
-                    pattern.lines().nth(matching_row).unwrap(),
-                    &mut inner_smudge_allowed)
-        }) && !inner_smudge_allowed
-    })
+fn find_root(groups: &mut [usize], mut ix: usize) -> usize {
+    while groups[ix] != ix {
+        groups[ix] = groups[groups[ix]];
+        ix = groups[ix];
+    }
+    ix
 }
 
-fn find_col_reflection(pattern: &str, smudge_allowed: bool) -> Option<usize> {
-    let num_cols = pattern.lines().next().unwrap().len();
-
-    (0..num_cols - 1).find(|&after_col| {
-        let mut inner_smudge_allowed = smudge_allowed;
-        (0..=after_col).all(|check_col| {
-            let matching_col = 2 * after_col - check_col + 1;
-            matching_col >= num_cols ||
-                patterns_match(
-                    &pattern.lines().map(|row| row.chars().nth(check_col).unwrap()).collect::<String>(),
-                    &pattern.lines().map(|row| row.chars().nth(matching_col).unwrap()).collect::<String>(),
-                    &mut inner_smudge_allowed)
-        }) && !inner_smudge_allowed
-    })
+// For each gap between lines that mirrors the lines either side of it, with at most `budget`
+// cells differing: how many lines come before it, and (line, bit) for each differing cell
+fn line_reflections(lines: &[u64], budget: u32) -> Vec<(usize, Vec<(usize, usize)>)> {
+    (1..lines.len()).filter_map(|after| {
+        let pairs = (0..after.min(lines.len() - after)).map(|offset| (after - 1 - offset, lines[after - 1 - offset] ^ lines[after + offset]));
+        let mut differences = 0;
+        let mut smudges = vec![];
+        for (line, mut diff) in pairs {
+            differences += diff.count_ones();
+            if differences > budget {
+                return None;
+            }
+            while diff != 0 {
+                smudges.push((line, diff.trailing_zeros() as usize));
+                diff &= diff - 1;
+            }
+        }
+        Some((after, smudges))
+    }).collect()
 }
 
-fn patterns_match(left: &str, right: &str, smudge_allowed: &mut bool) -> bool {
-    let errors = if *smudge_allowed { 1 } else { 0 };
-    let err_count = left.chars().zip(right.chars()).filter(|(l, r)| l != r).count();
+fn part_one(patterns: &[Pattern]) -> u32 {
+    patterns.iter().map(|pattern| pattern.reflections(0)[0].mirror.summary()).sum()
+}
+
+// Exactly one smudge has to be fixed, so the clean reflection doesn't count
+fn part_two(patterns: &[Pattern]) -> u32 {
+    patterns.iter().map(|pattern| {
+        pattern.reflections(1).iter().find(|reflection| reflection.smudges.len() == 1).unwrap().mirror.summary()
+    }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "#.##..##.
+..#.##.#.
+##......#
+##......#
+..#.##.#.
+..##..##.
+#.#.##.#.
+
+#...##..#
+#....#..#
+..##..###
+#####.##.
+#####.##.
+..##..###
+#....#..#";
+
+    fn mirrors(pattern: &str, budget: u32) -> Vec<(MirrorLoc, Vec<(usize, usize)>)> {
+        Pattern::parse(pattern).reflections(budget).into_iter().map(|reflection| (reflection.mirror, reflection.smudges)).collect()
+    }
+
+    #[test]
+    fn test_example() {
+        let patterns = EXAMPLE.split("\n\n").map(Pattern::parse).collect::<Vec<Pattern>>();
+        assert_eq!(part_one(&patterns), 405);
+        assert_eq!(part_two(&patterns), 400);
+    }
+
+    #[test]
+    fn test_example_smudges() {
+        let (first, second) = EXAMPLE.split_once("\n\n").unwrap();
+        // Rows 0 and 5 differ in column 0
+        assert_eq!(mirrors(first, 1), vec![(MirrorLoc::Column(5), vec![]), (MirrorLoc::Row(3), vec![(0, 0)])]);
+        // Rows 0 and 1 differ in column 4; the cell in the row above the line is the one reported
+        assert_eq!(mirrors(second, 1), vec![(MirrorLoc::Row(1), vec![(0, 4)]), (MirrorLoc::Row(4), vec![])]);
+    }
+
+    #[test]
+    fn test_several_mirrors() {
+        assert_eq!(mirrors("#..#\n#..#", 0), vec![(MirrorLoc::Column(2), vec![]), (MirrorLoc::Row(1), vec![])]);
+        assert_eq!(mirrors("##..##", 0), vec![
+            (MirrorLoc::Column(1), vec![]),
+            (MirrorLoc::Column(3), vec![]),
+            (MirrorLoc::Column(5), vec![])
+        ]);
+        // Smudges are reported as (row, col) for vertical mirrors too, in the column left of the line
+        assert_eq!(mirrors("#..#\n#...", 1), vec![
+            (MirrorLoc::Column(2), vec![(1, 0)]),
+            (MirrorLoc::Column(3), vec![(0, 2)]),
+            (MirrorLoc::Row(1), vec![(0, 3)])
+        ]);
+        assert_eq!(mirrors("#..#\n#...", 2).len(), 4);
+    }
+
+    fn found(pattern: &str, budget: u32) -> Vec<(Symmetry, Vec<(usize, usize)>)> {
+        Pattern::parse(pattern).symmetries(budget).into_iter().map(|found| (found.symmetry, found.smudges)).collect()
+    }
 
-    if err_count > errors {
-        false
-    } else {
-        if err_count == 1 { *smudge_allowed = false }
-        true
+    #[test]
+    fn test_example_symmetries() {
+        // The half turn is the column mirror combined with the rows mirrored between rows 2 and 3,
+        // which only disagree in column 0, whose image is off the pattern
+        let pattern = "#.##..##.\n..#.##.#.\n##......#\n##......#\n..#.##.#.\n..##..##.\n#.#.##.#.";
+        assert_eq!(found(pattern, 0), vec![
+            (Symmetry::Mirror(MirrorLoc::Column(5)), vec![]),
+            (Symmetry::HalfTurn { centre: (5, 9) }, vec![])
+        ]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_off_centre_symmetries() {
+        // A plus in the left three columns, so every symmetry is about its centre, not the pattern's
+        assert_eq!(found(".#..\n###.\n.#..", 0), vec![
+            (Symmetry::Diagonal { offset: 0 }, vec![]),
+            (Symmetry::AntiDiagonal { sum: 2 }, vec![]),
+            (Symmetry::QuarterTurn { centre: (2, 2) }, vec![]),
+            (Symmetry::HalfTurn { centre: (2, 2) }, vec![])
+        ]);
+
+        let smudged = ".#..\n###.\n.##.";
+        assert_eq!(found(smudged, 0), vec![(Symmetry::Diagonal { offset: 0 }, vec![])]);
+        let one_smudge = found(smudged, 1);
+        assert!(one_smudge.contains(&(Symmetry::QuarterTurn { centre: (2, 2) }, vec![(2, 2)])));
+        // Turning about the middle of the bottom-right 2x2 block drops the top row off the pattern
+        assert!(one_smudge.contains(&(Symmetry::HalfTurn { centre: (3, 3) }, vec![(1, 0)])));
+    }
+
+    #[test]
+    fn test_half_turns() {
+        assert_eq!(found("##.\n.#.\n.##", 0), vec![(Symmetry::HalfTurn { centre: (2, 2) }, vec![])]);
+        // One smudge away, with the earlier cell of the pair reported
+        assert!(found("##.\n.#.\n.#.", 1).contains(&(Symmetry::HalfTurn { centre: (2, 2) }, vec![(0, 0)])));
+        assert_eq!(found("#.#\n#.#", 0), vec![
+            (Symmetry::Mirror(MirrorLoc::Row(1)), vec![]),
+            (Symmetry::HalfTurn { centre: (1, 2) }, vec![])
+        ]);
+    }
+
+    #[test]
+    fn test_minimum_overlap() {
+        // The mirror after column 1 pairs up two cells and counts, like the puzzle's mirrors, but
+        // the half turn between the same two cells keeps only half the pattern on it
+        assert_eq!(found("##..", 0), vec![
+            (Symmetry::Mirror(MirrorLoc::Column(1)), vec![]),
+            (Symmetry::Mirror(MirrorLoc::Column(3)), vec![])
+        ]);
+        assert!(!found("##..", 1).iter().any(|(symmetry, _)| *symmetry == Symmetry::HalfTurn { centre: (0, 1) }));
+        // Turning about the corner shared by the top-left four cells keeps six of the nine on it
+        let quarter_turns = found("##.\n##.\n...", 0)
+            .into_iter()
+            .filter(|(symmetry, _)| matches!(symmetry, Symmetry::QuarterTurn { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(quarter_turns, vec![(Symmetry::QuarterTurn { centre: (1, 1) }, vec![])]);
+    }
+}
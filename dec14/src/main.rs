@@ -1,179 +1,216 @@
-use std::{collections::HashMap, iter};
+use std::{collections::{hash_map::DefaultHasher, HashMap}, env, hash::{Hash, Hasher}, ops::Range};
 
 use input_curler::input_for;
 
 fn main() {
     let data = input_for(14).unwrap();
+    let platform = Platform::parse(&data);
 
-    let answer_one = part_one(&data);
+    let answer_one = part_one(&platform);
     println!("Part one: {}", answer_one);
 
-    let answer_two = part_two(&data);
+    let answer_two = part_two(&platform);
     println!("Part two: {}", answer_two);
-}
-
-fn part_one(data: &str) -> i32 {
-    let width = data.lines().next().unwrap().len();
-    let height = data.lines().count();
-
-    (0..width).map(|col| {
-        let mut moment = height as i32;
-        let mut weight = 0;
-
-        for (row, line) in data.lines().enumerate() {
-            let cell = line.chars().nth(col).unwrap();
-            match cell {
-                '.' => {},
-                'O' => {
-                    weight += moment;
-                    moment -= 1;
-                },
-                '#' => {
-                    moment = (height - row - 1) as i32;
-                },
-                _ => unreachable!()
-            }
-        }
 
-        weight
-    }).sum()
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Some(cycles) = args.iter().position(|arg| arg == "--cycles").and_then(|ix| args.get(ix + 1)) {
+        let mut spun = platform.clone();
+        spun.spin(cycles.parse::<u64>().unwrap());
+        println!("Load after {} cycles: {}", cycles, spun.north_load());
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
-enum RockType {
-    Fixed,
-    Rolling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    West,
+    South,
+    East
 }
-use RockType::*;
-use itertools::Itertools;
-
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
-struct Rock {
-    x: i32,
-    y: i32,
-    rock_type: RockType
+use Direction::*;
+
+#[derive(Debug, Clone)]
+struct Platform {
+    height: usize,
+    // Bit x of rows[y] is set where there's a rounded rock. Cube rocks never move, so all that's
+    // kept of them is where they split each row and column into stretches rocks can roll along.
+    rows: Vec<u128>,
+    row_stretches: Vec<Vec<Range<usize>>>,
+    column_stretches: Vec<Vec<Range<usize>>>
 }
+impl Platform {
+    fn parse(data: &str) -> Self {
+        let grid = data.lines().map(|line| line.as_bytes()).collect::<Vec<&[u8]>>();
+        let (height, width) = (grid.len(), grid[0].len());
+        assert!(height <= 128 && width <= 128, "Platforms can be at most 128 by 128");
+
+        let rows = grid.iter().map(|line| {
+            line.iter().enumerate().filter(|(_, &cell)| cell == b'O').fold(0, |row, (x, _)| row | 1 << x)
+        }).collect();
+        let row_stretches = (0..height).map(|y| stretches((0..width).map(|x| grid[y][x]))).collect();
+        let column_stretches = (0..width).map(|x| stretches((0..height).map(|y| grid[y][x]))).collect();
 
-fn part_two(data: &str) -> i32 {
-    let height = data.lines().count();
-    let width = data.lines().next().unwrap().len();
-    let mut rocks = data.lines().enumerate().flat_map(|(row, line)| {
-        line.chars().enumerate().filter_map(|(col, cell)| {
-            match cell {
-                'O' => Some(Rock { y: row as i32, x: col as i32, rock_type: Rolling }),
-                '#' => Some(Rock { y: row as i32, x: col as i32, rock_type: Fixed }),
-                '.' => None,
-                _ => unreachable!()
+        Self { height, rows, row_stretches, column_stretches }
+    }
+
+    fn tilt(&mut self, direction: Direction) {
+        match direction {
+            West | East => roll(&mut self.rows, &self.row_stretches, direction == West),
+            North | South => {
+                let mut columns = transpose(&self.rows, self.column_stretches.len());
+                roll(&mut columns, &self.column_stretches, direction == North);
+                self.rows = transpose(&columns, self.height);
             }
-        }).collect::<Vec<Rock>>()
-    }).collect::<Vec<Rock>>();
-
-    let mut seen = HashMap::<Vec<Rock>, i32>::new();
-    let mut iteration = 0i32;
-    let limit = 1_000_000_000;
-    while iteration < limit {
-        rocks = (0..width).flat_map(|col| {
-            roll_rocks_towards_iter_start(
-                rocks
-                    .iter()
-                    .filter(|rock| rock.x == col as i32)
-                    .sorted_by_key(|rock| rock.y),
-                (0, col as i32),
-                    true,
-                    true
-            )
-        }).collect();
-        rocks = (0..height).flat_map(|row| {
-            roll_rocks_towards_iter_start(
-                rocks
-                    .iter()
-                    .filter(|rock| rock.y == row as i32)
-                    .sorted_by_key(|rock| rock.x),
-                    (row as i32, 0),
-                        false,
-                        true
-            )
-        }).collect();
-        rocks = (0..width).flat_map(|col| {
-            roll_rocks_towards_iter_start(
-                rocks
-                    .iter()
-                    .filter(|rock| rock.x == col as i32)
-                    .sorted_by_key(|rock| rock.y)
-                    .rev(),
-                    (height as i32 - 1, col as i32),
-                        true,
-                        false
-            )
-        }).collect();
-        rocks = (0..height).flat_map(|row| {
-            roll_rocks_towards_iter_start(
-                rocks
-                    .iter()
-                    .filter(|rock| rock.y == row as i32)
-                    .sorted_by_key(|rock| rock.x)
-                    .rev(),
-                    (row as i32, width as i32 - 1),
-                        false,
-                        false
-            )
-        }).collect();
-        rocks.sort();
+        }
+    }
+
+    fn spin_cycle(&mut self) {
+        for direction in [North, West, South, East] {
+            self.tilt(direction);
+        }
+    }
 
-        if let Some(when) = seen.get(&rocks) {
-            let period = iteration - when;
-            while (iteration + period) < limit {
-                iteration += period;
+    // Spins until a layout repeats, then skips whole periods. Layouts are looked up by hash, and
+    // only a full comparison with the earlier layout counts as a repeat.
+    fn spin(&mut self, cycles: u64) {
+        let mut history = vec![self.rows.clone()];
+        let mut seen = HashMap::<u64, Vec<usize>>::from([(state_hash(&self.rows), vec![0])]);
+        for done in 1..=cycles {
+            self.spin_cycle();
+            let hash = state_hash(&self.rows);
+            let earlier = seen.get(&hash).and_then(|candidates| candidates.iter().find(|&&ix| history[ix] == self.rows));
+            if let Some(&first) = earlier {
+                let period = done - first as u64;
+                let offset = (cycles - first as u64) % period;
+                self.rows = history[first + offset as usize].clone();
+                return;
             }
-        } else {
-            seen.insert(rocks.clone(), iteration);
+            seen.entry(hash).or_default().push(history.len());
+            history.push(self.rows.clone());
         }
-        iteration += 1;
     }
 
-    score_rocks(&rocks, height as i32)
+    fn north_load(&self) -> usize {
+        self.rows.iter().enumerate().map(|(y, row)| row.count_ones() as usize * (self.height - y)).sum()
+    }
 }
 
-fn roll_rocks_towards_iter_start<'a, Iter>(
-    rocks: Iter,
-    first_coords: (i32, i32),
-    changing_y: bool,
-    increasing: bool) -> Vec<Rock>
-where Iter: Iterator<Item = &'a Rock>
-{
-    let mut next_coords = first_coords;
-
-    let rocks_vec: Vec<&Rock> = rocks.collect();
-
-    rocks_vec.iter().map(|r| {
-        let cur_coords = next_coords;
-        if r.rock_type == Rolling {
-            if changing_y {
-                next_coords.0 += if increasing { 1 } else { -1 };
-            } else {
-                next_coords.1 += if increasing { 1 } else { -1 };
-            }
+// The runs of cells between cube rocks along a line
+fn stretches(line: impl Iterator<Item = u8>) -> Vec<Range<usize>> {
+    let mut stretches = vec![];
+    let mut start = 0;
+    let mut len = 0;
+    for (ix, cell) in line.enumerate() {
+        if cell == b'#' {
+            stretches.push(start..ix);
+            start = ix + 1;
+        }
+        len = ix + 1;
+    }
+    stretches.push(start..len);
+    stretches.retain(|stretch| !stretch.is_empty());
+    stretches
+}
 
-            Rock {
-                y: cur_coords.0,
-                x: cur_coords.1,
-                rock_type: Rolling
-            }
-        } else {
-            if changing_y {
-                next_coords.0 = r.y + if increasing { 1 } else { -1 };
+// Packs the rocks in each stretch up against its low end (towards bit 0) or its high end
+fn roll(lines: &mut [u128], stretches: &[Vec<Range<usize>>], towards_low: bool) {
+    for (line, line_stretches) in lines.iter_mut().zip(stretches) {
+        for stretch in line_stretches {
+            let mask = u128::MAX >> (128 - stretch.len()) << stretch.start;
+            let rocks = (*line & mask).count_ones() as usize;
+            let packed = if rocks == 0 {
+                0
+            } else if towards_low {
+                u128::MAX >> (128 - rocks) << stretch.start
             } else {
-                next_coords.1 = r.x + if increasing { 1 } else { -1 };
-            }
-            **r
+                u128::MAX >> (128 - rocks) << (stretch.end - rocks)
+            };
+            *line = *line & !mask | packed;
+        }
+    }
+}
+
+// Swaps rows for columns: bit x of lines[y] becomes bit y of the result's line x
+fn transpose(lines: &[u128], width: usize) -> Vec<u128> {
+    let mut transposed = vec![0; width];
+    for (y, &line) in lines.iter().enumerate() {
+        let mut bits = line;
+        while bits != 0 {
+            transposed[bits.trailing_zeros() as usize] |= 1 << y;
+            bits &= bits - 1;
         }
-    }).collect()
+    }
+    transposed
+}
+
+fn state_hash(rows: &[u128]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    rows.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn part_one(platform: &Platform) -> usize {
+    let mut tilted = platform.clone();
+    tilted.tilt(North);
+    tilted.north_load()
+}
+
+fn part_two(platform: &Platform) -> usize {
+    let mut spun = platform.clone();
+    spun.spin(1_000_000_000);
+    spun.north_load()
 }
 
-fn score_rocks(rocks: &[Rock], height: i32) -> i32 {
-    rocks
-        .iter()
-        .filter(|r| r.rock_type == Rolling)
-        .map(|r| height - r.y)
-        .sum()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "O....#....
+O.OO#....#
+.....##...
+OO.#O....O
+.O.....O#.
+O.#..O.#.#
+..O..#O..O
+.......O..
+#....###..
+#OO..#....";
+
+    #[test]
+    fn test_example() {
+        let platform = Platform::parse(EXAMPLE);
+        assert_eq!(part_one(&platform), 136);
+        assert_eq!(part_two(&platform), 64);
+    }
+
+    #[test]
+    fn test_spin_cycles() {
+        let platform = Platform::parse(EXAMPLE);
+        let mut unspun = platform.clone();
+        unspun.spin(0);
+        assert_eq!(unspun.rows, platform.rows);
+
+        let mut once = platform.clone();
+        once.spin(1);
+        let expected = ".....#....
+....#...O#
+...OO##...
+.OO#......
+.....OOO#.
+.O#...O#.#
+....O#....
+......OOOO
+#...O###..
+#..OO#....";
+        assert_eq!(once.rows, Platform::parse(expected).rows);
+
+        // Skipping ahead through the cycle has to land where spinning one at a time does
+        let mut stepped = platform.clone();
+        for cycles in 0..40 {
+            let mut skipped = platform.clone();
+            skipped.spin(cycles);
+            assert_eq!(skipped.rows, stepped.rows, "after {} cycles", cycles);
+            stepped.spin_cycle();
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use std::{collections::HashMap, ops::RangeInclusive};
+use std::collections::HashMap;
 use input_curler::input_for;
+use intervals::AxisBox;
 use regex::Regex;
 use Action::*;
 
@@ -42,7 +43,7 @@ struct Workflow<'a> {
     fallback_action: Action<'a>
 }
 impl<'a> Workflow<'a> {
-    fn apply(&self, part: &Part) -> Action {
+    fn apply(&self, part: &Part) -> Action<'a> {
         let matching_rule = self.rules.iter().find(|rule| (rule.check)(part));
         if let Some(rule) = matching_rule {
             rule.action
@@ -53,38 +54,17 @@ impl<'a> Workflow<'a> {
 }
 
 fn main() {
-    let data = "px{a<2006:qkq,m>2090:A,rfg}
-pv{a>1716:R,A}
-lnx{m>1548:A,A}
-rfg{s<537:gd,x>2440:R,A}
-qs{s>3448:A,lnx}
-qkq{x<1416:A,crn}
-crn{x>2662:A,R}
-in{s<1351:px,qqz}
-qqz{s>2770:qs,m<1801:hdj,R}
-gd{a>3333:R,R}
-hdj{m>838:A,pv}
-
-{x=787,m=2655,a=1222,s=2876}
-{x=1679,m=44,a=2067,s=496}
-{x=2036,m=264,a=79,s=2244}
-{x=2461,m=1339,a=466,s=291}
-{x=2127,m=1623,a=2188,s=1013}".to_string();
     let data = input_for(19).unwrap();
 
     let (workflows, parts) = parse_data(&data);
-    let answer_one = parts
-        .iter()
-        .filter(|part| accept_by_workflows(part, &workflows))
-        .map(|part| part.x + part.m + part.a + part.s)
-        .sum::<u32>();
+    let answer_one = part_one(&workflows, &parts);
     println!("Part one: {}", answer_one);
 
     let answer_two = part_two(&workflows);
     println!("Part two: {}", answer_two);
 }
 
-fn parse_data(data: &str) -> (HashMap<&str, Workflow>, Vec<Part>) {
+fn parse_data(data: &str) -> (HashMap<&str, Workflow<'_>>, Vec<Part>) {
     let mut lines = data.lines();
 
     let workflows = lines
@@ -110,7 +90,7 @@ fn parse_data(data: &str) -> (HashMap<&str, Workflow>, Vec<Part>) {
     (workflows, parts)
 }
 
-fn parse_workflow(data: &str) -> (&str, Workflow) {
+fn parse_workflow(data: &str) -> (&str, Workflow<'_>) {
     let workflow_regex = Regex::new(r"^(?<name>\w+)\{(?<rules>.*)\}$").unwrap();
     let captures = workflow_regex.captures(data).unwrap();
     let name = captures.name("name").unwrap().as_str();
@@ -125,7 +105,7 @@ fn parse_workflow(data: &str) -> (&str, Workflow) {
     })
 }
 
-fn parse_rule(data: &str) -> Rule {
+fn parse_rule(data: &str) -> Rule<'_> {
     let (check_part, action_part) = data.split_once(':').unwrap();
     let mut chars = check_part.chars();
     let field = chars.next().unwrap();
@@ -168,82 +148,90 @@ fn accept_by_workflows(part: &Part, workflows: &HashMap<&str, Workflow>) -> bool
     }
 }
 
+fn part_one(workflows: &HashMap<&str, Workflow>, parts: &[Part]) -> u32 {
+    parts
+        .iter()
+        .filter(|part| accept_by_workflows(part, workflows))
+        .map(|part| part.x + part.m + part.a + part.s)
+        .sum()
+}
+
 fn part_two(workflows: &HashMap<&str, Workflow>) -> u64 {
-    let mut might_accept: Vec<([RangeInclusive<u32>; 4], &str)> = vec![
-        ([1..=4000, 1..=4000, 1..=4000, 1..=4000], "in")
+    let mut might_accept: Vec<(AxisBox<u32, 4>, &str)> = vec![
+        (AxisBox::new([1..4001, 1..4001, 1..4001, 1..4001]), "in")
     ];
-    let mut accepted: Vec<[RangeInclusive<u32>; 4]> = vec![];
+    let mut accepted: Vec<AxisBox<u32, 4>> = vec![];
 
-    while let Some(mut chunk) = might_accept.pop() {
-        let workflow = workflows.get(chunk.1).unwrap();
-        let mut fallthrough = true;
+    while let Some((chunk, name)) = might_accept.pop() {
+        let workflow = workflows.get(name).unwrap();
+        let mut remainder = Some(chunk);
         for rule in workflow.rules.iter() {
-            let range_of_interest_ix = match rule.field {
+            let Some(chunk) = remainder else { break };
+            let axis = match rule.field {
                 'x' => 0,
                 'm' => 1,
                 'a' => 2,
                 's' => 3,
                 _ => unreachable!()
             };
-            let range_of_interest = &mut chunk.0[range_of_interest_ix];
-            let split_range = range_of_interest.contains(&rule.check_value);
-
-            if split_range {
-                // Part of the range satisfies the condition. Add the sub-range that does so onto might_accept
-                // for the next step (or accept or reject it); then continue down workflow.rules with the remainder
-                if rule.op == '<' {
-                    let matched_part = *range_of_interest.start()..=(rule.check_value - 1);
-                    let unmatched_part = rule.check_value..=*range_of_interest.end();
-                    let mut matched_chunk = chunk.0.clone();
-                    matched_chunk[range_of_interest_ix] = matched_part;
-                    let mut unmatched_chunk = chunk.0.clone();
-                    unmatched_chunk[range_of_interest_ix] = unmatched_part;
-                    match rule.action {
-                        Accept => accepted.push(matched_chunk),
-                        Reject => {},
-                        Switch(name) => might_accept.push((matched_chunk, name))
-                    };
-                    chunk.0 = unmatched_chunk;
-                } else if rule.op == '>' {
-                    let unmatched_part = *range_of_interest.start()..=rule.check_value;
-                    let matched_part = (rule.check_value + 1)..=*range_of_interest.end();
-                    let mut matched_chunk = chunk.0.clone();
-                    matched_chunk[range_of_interest_ix] = matched_part;
-                    let mut unmatched_chunk = chunk.0.clone();
-                    unmatched_chunk[range_of_interest_ix] = unmatched_part;
-                    match rule.action {
-                        Accept => accepted.push(matched_chunk),
-                        Reject => {},
-                        Switch(name) => might_accept.push((matched_chunk, name))
-                    };
-                    chunk.0 = unmatched_chunk;
-                }
-            } else {
-                // Either the whole range satisfies, or the whole range doesn't. Based on whether start does,
-                // either push the range back onto might_accept for the next step (or accept or reject it); or
-                // continue down with the whole range.
-                if (rule.op == '<' && range_of_interest.start() < &rule.check_value) ||
-                    (rule.op == '>' && range_of_interest.start() > &rule.check_value)
-                {
-                    match rule.action {
-                        Accept => accepted.push(chunk.clone().0),
-                        Reject => {},
-                        Switch(name) => might_accept.push((chunk.clone().0, name))
-                    };
-                    fallthrough = false;
-                    break;
-                }
+
+            // Cut the chunk into the part that satisfies the condition, which goes on to the next step (or is
+            // accepted or rejected); and the remainder, which continues down workflow.rules
+            let (matched, unmatched) = match rule.op {
+                '<' => chunk.split(axis, rule.check_value),
+                '>' => {
+                    let (below, above) = chunk.split(axis, rule.check_value + 1);
+                    (above, below)
+                },
+                _ => unreachable!()
+            };
+            if let Some(matched) = matched {
+                match rule.action {
+                    Accept => accepted.push(matched),
+                    Reject => {},
+                    Switch(name) => might_accept.push((matched, name))
+                };
             }
+            remainder = unmatched;
         }
 
-        if fallthrough {
+        if let Some(chunk) = remainder {
             match workflow.fallback_action {
-                Accept => accepted.push(chunk.0),
+                Accept => accepted.push(chunk),
                 Reject => {},
-                Switch(name) => might_accept.push((chunk.0, name))
+                Switch(name) => might_accept.push((chunk, name))
             };
         }
     }
 
-    accepted.iter().map(|chunk| chunk.iter().map(|range| (range.end() - range.start() + 1) as u64).product::<u64>()).sum()
-}
\ No newline at end of file
+    accepted.iter().map(|chunk| chunk.volume::<u64>()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example() {
+        let data = "px{a<2006:qkq,m>2090:A,rfg}
+pv{a>1716:R,A}
+lnx{m>1548:A,A}
+rfg{s<537:gd,x>2440:R,A}
+qs{s>3448:A,lnx}
+qkq{x<1416:A,crn}
+crn{x>2662:A,R}
+in{s<1351:px,qqz}
+qqz{s>2770:qs,m<1801:hdj,R}
+gd{a>3333:R,R}
+hdj{m>838:A,pv}
+
+{x=787,m=2655,a=1222,s=2876}
+{x=1679,m=44,a=2067,s=496}
+{x=2036,m=264,a=79,s=2244}
+{x=2461,m=1339,a=466,s=291}
+{x=2127,m=1623,a=2188,s=1013}";
+        let (workflows, parts) = parse_data(data);
+        assert_eq!(part_one(&workflows, &parts), 19114);
+        assert_eq!(part_two(&workflows), 167409079868000);
+    }
+}
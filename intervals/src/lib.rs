@@ -0,0 +1,210 @@
+use std::{iter::Product, ops::{Add, Range, Sub}};
+
+// A set of values stored as sorted, disjoint, non-touching half-open ranges.
+// Every constructor normalizes, so two sets with the same members compare equal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T> {
+    ranges: Vec<Range<T>>
+}
+
+impl<T: Copy + Ord> Default for IntervalSet<T> {
+    fn default() -> Self {
+        Self { ranges: vec![] }
+    }
+}
+
+impl<T: Copy + Ord> IntervalSet<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Sorts the ranges, drops empty ones and merges any that overlap or touch
+    pub fn normalize(ranges: Vec<Range<T>>) -> Self {
+        let mut ranges = ranges.into_iter().filter(|r| r.start < r.end).collect::<Vec<_>>();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged = Vec::<Range<T>>::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range)
+            }
+        }
+        Self { ranges: merged }
+    }
+
+    pub fn ranges(&self) -> &[Range<T>] {
+        &self.ranges
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Range<T>> {
+        self.ranges.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn min(&self) -> Option<T> {
+        self.ranges.first().map(|r| r.start)
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        let ix = self.ranges.partition_point(|r| r.end <= *value);
+        self.ranges.get(ix).is_some_and(|r| r.contains(value))
+    }
+
+    pub fn insert(&mut self, range: Range<T>) {
+        let mut ranges = std::mem::take(&mut self.ranges);
+        ranges.push(range);
+        *self = Self::normalize(ranges);
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::normalize(self.ranges.iter().chain(other.ranges.iter()).cloned().collect())
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut result = vec![];
+        let (mut ix, mut other_ix) = (0, 0);
+        while let (Some(ours), Some(theirs)) = (self.ranges.get(ix), other.ranges.get(other_ix)) {
+            let start = ours.start.max(theirs.start);
+            let end = ours.end.min(theirs.end);
+            if start < end {
+                result.push(start..end);
+            }
+            if ours.end < theirs.end { ix += 1 } else { other_ix += 1 }
+        }
+        Self { ranges: result }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut result = vec![];
+        let mut other_ix = 0;
+        for range in &self.ranges {
+            let mut start = range.start;
+            while other_ix < other.ranges.len() && other.ranges[other_ix].end <= start {
+                other_ix += 1;
+            }
+            for cut in &other.ranges[other_ix..] {
+                if cut.start >= range.end {
+                    break;
+                }
+                if cut.start > start {
+                    result.push(start..cut.start);
+                }
+                start = start.max(cut.end);
+            }
+            if start < range.end {
+                result.push(start..range.end);
+            }
+        }
+        Self { ranges: result }
+    }
+}
+
+impl<T: Copy + Ord + Default + Add<Output = T> + Sub<Output = T>> IntervalSet<T> {
+    pub fn total_length(&self) -> T {
+        self.ranges.iter().fold(T::default(), |total, r| total + (r.end - r.start))
+    }
+}
+
+impl<T: Copy + Ord> From<Range<T>> for IntervalSet<T> {
+    fn from(range: Range<T>) -> Self {
+        Self::normalize(vec![range])
+    }
+}
+
+impl<T: Copy + Ord> FromIterator<Range<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Range<T>>>(iter: I) -> Self {
+        Self::normalize(iter.into_iter().collect())
+    }
+}
+
+impl<T> IntoIterator for IntervalSet<T> {
+    type Item = Range<T>;
+    type IntoIter = std::vec::IntoIter<Range<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter()
+    }
+}
+
+// An axis-aligned box in N dimensions, half-open along every axis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AxisBox<T, const N: usize> {
+    pub axes: [Range<T>; N]
+}
+
+impl<T: Copy + Ord, const N: usize> AxisBox<T, N> {
+    pub fn new(axes: [Range<T>; N]) -> Self {
+        Self { axes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.axes.iter().any(|r| r.start >= r.end)
+    }
+
+    pub fn contains(&self, point: &[T; N]) -> bool {
+        self.axes.iter().zip(point).all(|(r, value)| r.contains(value))
+    }
+
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let mut axes = self.axes.clone();
+        for (axis, theirs) in axes.iter_mut().zip(&other.axes) {
+            *axis = axis.start.max(theirs.start)..axis.end.min(theirs.end);
+        }
+        let result = Self { axes };
+        (!result.is_empty()).then_some(result)
+    }
+
+    // Cuts the box with the hyperplane `axis == at`, returning the part below the
+    // plane and the part at or above it. Either side is None if it would be empty.
+    pub fn split(&self, axis: usize, at: T) -> (Option<Self>, Option<Self>) {
+        let range = &self.axes[axis];
+        let mut below = self.clone();
+        below.axes[axis] = range.start..at.clamp(range.start, range.end);
+        let mut above = self.clone();
+        above.axes[axis] = at.clamp(range.start, range.end)..range.end;
+
+        ((!below.is_empty()).then_some(below), (!above.is_empty()).then_some(above))
+    }
+
+    pub fn volume<V: From<T> + Product>(&self) -> V where T: Sub<Output = T> {
+        self.axes.iter().map(|r| V::from(r.end - r.start)).product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_merges_touching_and_overlapping() {
+        let set = IntervalSet::normalize(vec![5..8, 0..2, 2..3, 7..10, 4..4]);
+        assert_eq!(set.ranges(), &[0..3, 5..10]);
+        assert_eq!(set.total_length(), 8);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a = IntervalSet::from_iter([0..10, 20..30]);
+        let b = IntervalSet::from_iter([5..25, 28..40]);
+
+        let union = a.union(&b);
+        assert_eq!((union.ranges().len(), union.min(), union.total_length()), (1, Some(0), 40));
+        assert_eq!(a.intersection(&b).ranges(), &[5..10, 20..25, 28..30]);
+        assert_eq!(a.difference(&b).ranges(), &[0..5, 25..28]);
+        assert_eq!(b.difference(&a).ranges(), &[10..20, 30..40]);
+        assert!(a.contains(&29) && !a.contains(&30));
+    }
+
+    #[test]
+    fn test_box_split() {
+        let cube = AxisBox::new([0..10u32, 0..10, 0..10]);
+        let (below, above) = cube.split(1, 4);
+        assert_eq!(below.unwrap().axes, [0..10, 0..4, 0..10]);
+        assert_eq!(above.as_ref().unwrap().volume::<u64>(), 600);
+        assert_eq!(cube.split(0, 10), (Some(cube.clone()), None));
+    }
+}